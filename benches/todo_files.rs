@@ -0,0 +1,131 @@
+//! Benchmarks of reading and listing large todo files
+//!
+//! A todo.txt of 5,000 todos and an archive.txt of 50,000 are generated
+//! in a temporary directory. The library benchmarks parse and filter
+//! them, the command benchmarks run `todo-txt ls` and `todo-txt report`
+//! on them the way a user would.
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use todo_txt::todo_file::{parse_todos, parse_todos_containing};
+
+const TODO_COUNT: usize = 5_000;
+const ARCHIVE_COUNT: usize = 50_000;
+
+const PROJECTS: &[&str] = &["Garden", "Taxes", "Website", "Family", "Car"];
+const CONTEXTS: &[&str] = &["home", "phone", "computer", "errands"];
+
+/// Line `i` of a synthetic todo file, a mix of priorities, dates,
+/// projects, contexts, tags and key values
+fn line(i: usize, complete: bool) -> String {
+	let mut words = Vec::new();
+
+	if complete {
+		words.push(format!("x 2026-{:02}-{:02}", i % 12 + 1, i % 28 + 1));
+	} else if i % 3 == 0 {
+		words.push(format!("({})", (b'A' + (i % 4) as u8) as char));
+	}
+
+	words.push(format!("2025-{:02}-{:02}", i % 12 + 1, i % 28 + 1));
+	words.push(format!("task number {} of the benchmark", i));
+	words.push(format!("+{}", PROJECTS[i % PROJECTS.len()]));
+	words.push(format!("@{}", CONTEXTS[i % CONTEXTS.len()]));
+
+	if i % 5 == 0 {
+		words.push(String::from("#someday"));
+	}
+
+	if i % 4 == 0 {
+		words.push(format!("due:2026-{:02}-{:02}", i % 12 + 1, i % 28 + 1));
+	}
+
+	if i % 7 == 0 {
+		words.push(format!("clocked:{}h{}m", i % 5, i % 60));
+	}
+
+	words.push(format!("id:{:08x}-4c0a-4c6e-9d3b-7a1f3b8d7c11", i));
+
+	words.join(" ")
+}
+
+fn write_lines(path: &Path, count: usize, complete: bool) {
+	let text: String = (0..count)
+		.map(|i| format!("{}\n", line(i, complete)))
+		.collect();
+
+	fs::write(path, text).unwrap();
+}
+
+/// Directory with the todo files and a configuration using them
+fn data_dir() -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("todo-txt-bench-{}", std::process::id()));
+
+	fs::create_dir_all(&dir).unwrap();
+	write_lines(&dir.join("todo.txt"), TODO_COUNT, false);
+	write_lines(&dir.join("archive.txt"), ARCHIVE_COUNT, true);
+	fs::write(
+		dir.join("cfg.toml"),
+		format!("data_path = {:?}\n", dir.to_string_lossy()),
+	)
+	.unwrap();
+
+	dir
+}
+
+fn run(dir: &Path, args: &[&str]) {
+	let output = Command::new(env!("CARGO_BIN_EXE_todo-txt"))
+		.arg("--config")
+		.arg(dir.join("cfg.toml"))
+		.args(args)
+		.output()
+		.unwrap();
+
+	assert!(output.status.success(), "todo-txt {:?} failed", args);
+}
+
+fn library(c: &mut Criterion) {
+	let dir = data_dir();
+	let archive = dir.join("archive.txt").to_string_lossy().to_string();
+	let mut group = c.benchmark_group("library");
+
+	group.sample_size(20);
+	group.bench_function("parse archive", |b| {
+		b.iter(|| parse_todos(&archive).unwrap())
+	});
+	group.bench_function("parse archive containing +Garden", |b| {
+		b.iter(|| parse_todos_containing(&archive, &[String::from("+Garden")]).unwrap())
+	});
+	group.bench_function("filter archive by text", |b| {
+		b.iter_batched(
+			|| parse_todos(&archive).unwrap(),
+			|list| list.filter_by_texts(&["+Garden", "@home", "-#someday"]),
+			BatchSize::LargeInput,
+		)
+	});
+	group.finish();
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+fn commands(c: &mut Criterion) {
+	let dir = data_dir();
+	let mut group = c.benchmark_group("commands");
+
+	group.sample_size(10);
+	group.bench_function("ls", |b| b.iter(|| run(&dir, &["ls"])));
+	group.bench_function("ls filtered", |b| {
+		b.iter(|| run(&dir, &["ls", "+Garden", "@home"]))
+	});
+	group.bench_function("ls --all", |b| b.iter(|| run(&dir, &["ls", "--all"])));
+	group.bench_function("report --all", |b| {
+		b.iter(|| run(&dir, &["report", "--all", "Garden"]))
+	});
+	group.finish();
+
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+criterion_group!(benches, library, commands);
+criterion_main!(benches);
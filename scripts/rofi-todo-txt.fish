@@ -30,13 +30,14 @@ if test $ROFI_RETV = 1
 
     set trimmed (string trim -l -r $argv)
     set parts (string split ":" $trimmed)
-    set number $parts[1]
-    set display_task (string trim -l -r $parts[2..])
+    set rest (string trim -l -r (string join ":" $parts[2..]))
+    set id (string split -m 1 " " $rest)[1]
+    set display_task (string split -m 1 " " $rest)[2]
 
     echo -en "$display_task\0nonselectable\x1ftrue\n"
-    echo -en "Toggle Complete State\0info\x1fTCS:$number\n"
-    echo -en "Check In/Out\0info\x1fCIO:$number\n"
-    echo -ne "Delete\0info\x1fDEL:$number\n"
+    echo -en "Toggle Complete State\0info\x1fTCS:$id\n"
+    echo -en "Check In/Out\0info\x1fCIO:$id\n"
+    echo -ne "Delete\0info\x1fDEL:$id\n"
 
     echo "Quit"
 
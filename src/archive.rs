@@ -0,0 +1,475 @@
+/// archive.txt, split into one file per period when it is rotated
+///
+/// With `archive_rotation` set, todos are archived to a file of the
+/// current period next to archive.txt, such as `archive-2026-10.txt`.
+/// Files of past periods are gzipped if `archive_compress` is set. An
+/// `Archive` reads an archive.txt and all of its rotated files as one.
+use chrono::{Local, NaiveDate};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::io::prelude::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::cfg::{get_archive_compress, get_archive_rotation, get_id_strategy, ArchiveRotation};
+use crate::encoding::{check_rewrite, decode, encode, Decoded, Format};
+use crate::journal::written_id;
+use crate::parse_error::ParseError;
+use crate::todo_file::write_file_atomically;
+
+lazy_static! {
+	static ref PERIOD_RE: Regex = Regex::new(r"^\d{4}(-\d{2})?$").unwrap();
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// One file of an archive
+pub struct ArchiveFile {
+	pub path: PathBuf,
+
+	/// Period the file holds, None for archive.txt itself
+	pub period: Option<String>,
+
+	/// true if the file is gzipped
+	pub compressed: bool,
+}
+
+/// An archive.txt and the files it was rotated into, read as one
+pub struct Archive {
+	filename: String,
+
+	/// Each file with the format it is written in and its lines
+	files: Vec<(ArchiveFile, Format, Vec<String>)>,
+
+	/// Invalid byte sequences found in the files
+	errors: Vec<(PathBuf, ParseError)>,
+}
+
+/// Period of todos archived on `date`, None if the archive is not rotated
+pub fn period(rotation: ArchiveRotation, date: NaiveDate) -> Option<String> {
+	match rotation {
+		ArchiveRotation::None => None,
+		ArchiveRotation::Monthly => Some(date.format("%Y-%m").to_string()),
+		ArchiveRotation::Yearly => Some(date.format("%Y").to_string()),
+	}
+}
+
+/// File of `period` for the archive `filename`, `archive-2026-10.txt`
+/// for `archive.txt`
+pub fn period_path(filename: &str, period: &str) -> PathBuf {
+	let path = Path::new(filename);
+	let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+	let name = match path.extension() {
+		Some(ext) => format!("{}-{}.{}", stem, period, ext.to_string_lossy()),
+		None => format!("{}-{}", stem, period),
+	};
+
+	path.with_file_name(name)
+}
+
+/// File of the archive `filename` that todos archived today go to
+pub fn current_file(filename: &str) -> ArchiveFile {
+	let period = period(get_archive_rotation(), Local::today().naive_local());
+	let path = match &period {
+		Some(p) => period_path(filename, p),
+		None => PathBuf::from(filename),
+	};
+
+	ArchiveFile {
+		path,
+		period,
+		compressed: false,
+	}
+}
+
+fn dir_of(path: &Path) -> &Path {
+	match path.parent() {
+		Some(p) if !p.as_os_str().is_empty() => p,
+		_ => Path::new("."),
+	}
+}
+
+/// The file named `name` next to the archive `filename` if it is one
+/// the archive was rotated into
+fn rotated_file(filename: &str, name: &str) -> Option<ArchiveFile> {
+	let path = Path::new(filename);
+	let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+	let extension = path
+		.extension()
+		.map(|e| format!(".{}", e.to_string_lossy()))
+		.unwrap_or_default();
+	let (plain_name, compressed) = match name.strip_suffix(".gz") {
+		Some(n) => (n, true),
+		None => (name, false),
+	};
+	let period = plain_name
+		.strip_prefix(stem.as_ref())
+		.and_then(|n| n.strip_prefix('-'))
+		.and_then(|n| n.strip_suffix(&extension))
+		.filter(|p| PERIOD_RE.is_match(p))?;
+
+	Some(ArchiveFile {
+		path: dir_of(path).join(name),
+		period: Some(period.to_string()),
+		compressed,
+	})
+}
+
+/// true if `path` is the archive `filename` or one of the files it may
+/// be rotated into
+pub fn is_archive_file(filename: &str, path: &Path) -> bool {
+	let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+	path == Path::new(filename)
+		|| (dir_of(path) == dir_of(Path::new(filename)) && rotated_file(filename, &name).is_some())
+}
+
+/// Files of the archive `filename`, oldest first: `filename` itself if
+/// it exists, then the files it was rotated into by period
+pub fn list_files(filename: &str) -> Result<Vec<ArchiveFile>, io::Error> {
+	let path = Path::new(filename);
+	let mut files = Vec::new();
+
+	if path.exists() {
+		files.push(ArchiveFile {
+			path: path.to_path_buf(),
+			period: None,
+			compressed: false,
+		});
+	}
+
+	let entries = match fs::read_dir(dir_of(path)) {
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(files),
+		Err(e) => return Err(e),
+		Ok(entries) => entries,
+	};
+	let mut rotated = Vec::new();
+
+	for entry in entries {
+		rotated.extend(rotated_file(
+			filename,
+			&entry?.file_name().to_string_lossy(),
+		));
+	}
+
+	// A plain file next to a gzipped one of the same period was not yet
+	// removed after compressing it. It is complete, so it is kept.
+	rotated.sort_by(|a, b| (&a.period, a.compressed).cmp(&(&b.period, b.compressed)));
+	rotated.dedup_by(|a, b| a.period == b.period);
+
+	files.extend(rotated);
+
+	Ok(files)
+}
+
+/// Content of `file`, uncompressed
+fn read_bytes(file: &ArchiveFile) -> Result<Vec<u8>, io::Error> {
+	let mut f = match File::open(&file.path) {
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+		Err(e) => return Err(e),
+		Ok(f) => f,
+	};
+	let mut bytes = Vec::new();
+
+	match file.compressed {
+		true => GzDecoder::new(f).read_to_end(&mut bytes)?,
+		false => f.read_to_end(&mut bytes)?,
+	};
+
+	Ok(bytes)
+}
+
+fn write_bytes(file: &ArchiveFile, bytes: &[u8]) -> Result<(), io::Error> {
+	write_file_atomically(&file.path.to_string_lossy(), |w| match file.compressed {
+		true => {
+			let mut gz = GzEncoder::new(w, Compression::default());
+			gz.write_all(bytes)?;
+			gz.finish().map(|_| ())
+		}
+		false => w.write_all(bytes),
+	})
+}
+
+fn read_file(file: &ArchiveFile) -> Result<Decoded, io::Error> {
+	Ok(decode(&read_bytes(file)?))
+}
+
+fn write_file(file: &ArchiveFile, text: &str, format: Format) -> Result<(), io::Error> {
+	write_bytes(file, &encode(text, format))
+}
+
+/// `line` without an id at its end, as added to lines that had none
+/// when they are written
+fn without_id(line: &str) -> Option<&str> {
+	let key = format!(" {}:", get_id_strategy().key()?);
+	let (text, id) = line.rsplit_once(&key)?;
+
+	match id.is_empty() || id.contains(char::is_whitespace) {
+		true => None,
+		false => Some(text),
+	}
+}
+
+fn join_lines(lines: &[String]) -> String {
+	lines.iter().map(|l| format!("{}\n", l)).collect()
+}
+
+/// Gzip the rotated files of the archive `filename` of periods before
+/// the current one, if `archive_compress` is set
+pub fn compress_old_files(filename: &str) -> Result<(), io::Error> {
+	if !get_archive_compress() {
+		return Ok(());
+	}
+
+	let current = current_file(filename);
+
+	for file in list_files(filename)? {
+		if file.compressed || file.period.is_none() || file.period == current.period {
+			continue;
+		}
+
+		let mut gz_path = file.path.clone().into_os_string();
+		gz_path.push(".gz");
+
+		let gz = ArchiveFile {
+			path: PathBuf::from(gz_path),
+			compressed: true,
+			..file.clone()
+		};
+
+		write_bytes(&gz, &read_bytes(&file)?)?;
+		fs::remove_file(&file.path)?;
+	}
+
+	Ok(())
+}
+
+impl Archive {
+	/// Read all files of the archive `filename`
+	pub fn open(filename: &str) -> Result<Archive, io::Error> {
+		let mut files = Vec::new();
+		let mut errors = Vec::new();
+
+		for file in list_files(filename)? {
+			let decoded = read_file(&file)?;
+			let lines = decoded.text.lines().map(String::from).collect();
+
+			errors.extend(decoded.errors.into_iter().map(|e| (file.path.clone(), e)));
+			files.push((file, decoded.format, lines));
+		}
+
+		Ok(Archive {
+			filename: filename.to_string(),
+			files,
+			errors,
+		})
+	}
+
+	/// Lines of all files, oldest first, one per line
+	pub fn text(&self) -> String {
+		self.files
+			.iter()
+			.map(|(_, _, lines)| join_lines(lines))
+			.collect()
+	}
+
+	/// Invalid byte sequences found in the files, and the file of each
+	pub fn decode_errors(&self) -> &[(PathBuf, ParseError)] {
+		&self.errors
+	}
+
+	/// Replace the lines of the archive with `lines`
+	///
+	/// A line that is already in one of the files stays in it, found by
+	/// its text, its id or its text without an id. Other lines go to the
+	/// current file.
+	/// Only files whose lines change are written, each atomically, and
+	/// rotated files left empty are removed. Nothing is written to a file
+	/// with invalid UTF-8, see `encoding::check_rewrite`.
+	pub fn replace(&mut self, lines: Vec<String>) -> Result<(), io::Error> {
+		let mut by_line: HashMap<&str, Vec<usize>> = HashMap::new();
+
+		// Changed lines are found by their id, or by their text without
+		// the id added to lines that had none. Parsing every line for its
+		// id is slow, so this is only done once a line is not found as is.
+		let mut changed = None;
+
+		for (i, (_, _, old)) in self.files.iter().enumerate() {
+			for l in old {
+				by_line.entry(l).or_default().push(i);
+			}
+		}
+
+		let mut owners = Vec::new();
+
+		for l in &lines {
+			let owner = match by_line.get_mut(l.as_str()).and_then(|i| i.pop()) {
+				Some(i) => Some(i),
+				None => {
+					let (by_id, by_text) = changed.get_or_insert_with(|| {
+						let mut by_id = HashMap::new();
+						let mut by_text = HashMap::new();
+
+						for (i, (_, _, old)) in self.files.iter().enumerate() {
+							for l in old {
+								if let Some(id) = written_id(l) {
+									by_id.insert(id, i);
+								}
+
+								by_text.insert(without_id(l).unwrap_or(l), i);
+							}
+						}
+
+						(by_id, by_text)
+					});
+
+					written_id(l)
+						.and_then(|id| by_id.get(&id))
+						.or_else(|| by_text.get(without_id(l).unwrap_or(l)))
+						.copied()
+				}
+			};
+
+			owners.push(owner);
+		}
+
+		// A new current file is written like the latest file was
+		let current = current_file(&self.filename);
+		let current_index = match self
+			.files
+			.iter()
+			.position(|(f, _, _)| f.path == current.path)
+		{
+			Some(i) => i,
+			None => {
+				let format = self.files.last().map(|(_, f, _)| *f).unwrap_or_default();
+
+				self.files.push((current, format, vec![]));
+				self.files.len() - 1
+			}
+		};
+
+		let mut new_lines = vec![vec![]; self.files.len()];
+
+		for (l, owner) in lines.into_iter().zip(owners) {
+			new_lines[owner.unwrap_or(current_index)].push(l);
+		}
+
+		for ((file, _, old), new) in self.files.iter().zip(&new_lines) {
+			if old != new {
+				let errors: Vec<ParseError> = self
+					.errors
+					.iter()
+					.filter(|(path, _)| *path == file.path)
+					.map(|(_, e)| e.clone())
+					.collect();
+
+				check_rewrite(&file.path, &errors)?;
+			}
+		}
+
+		for ((file, format, old), new) in self.files.iter_mut().zip(new_lines) {
+			if *old == new {
+				continue;
+			}
+
+			if new.is_empty() && file.period.is_some() {
+				match fs::remove_file(&file.path) {
+					Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+					_ => {}
+				}
+			} else {
+				write_file(file, &join_lines(&new), *format)?;
+			}
+
+			*old = new;
+		}
+
+		self.files
+			.retain(|(f, _, lines)| f.period.is_none() || !lines.is_empty());
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_util::scratch_dir;
+
+	#[test]
+	fn id_added_to_a_line_is_ignored() {
+		assert_eq!(
+			without_id("x call mom id:2a6a3d2e-4c0a-4c6e-9d3b-7a1f3b8d7c11"),
+			Some("x call mom")
+		);
+		assert_eq!(without_id("x call mom"), None);
+		assert_eq!(without_id("x call mom id: again"), None);
+	}
+
+	fn lines(text: &[&str]) -> Vec<String> {
+		text.iter().map(|l| l.to_string()).collect()
+	}
+
+	#[test]
+	fn period_filenames() {
+		let date = NaiveDate::from_ymd(2026, 10, 18);
+
+		assert_eq!(period(ArchiveRotation::None, date), None);
+		assert_eq!(
+			period(ArchiveRotation::Monthly, date),
+			Some(String::from("2026-10"))
+		);
+		assert_eq!(
+			period(ArchiveRotation::Yearly, date),
+			Some(String::from("2026"))
+		);
+		assert_eq!(
+			period_path("/data/archive.txt", "2026-10"),
+			PathBuf::from("/data/archive-2026-10.txt")
+		);
+		assert_eq!(
+			period_path("/data/work.archive.txt", "2026"),
+			PathBuf::from("/data/work.archive-2026.txt")
+		);
+	}
+
+	#[test]
+	fn archive_spans_rotated_files() {
+		let dir = scratch_dir("archive-spans");
+		let filename = dir.join("archive.txt").to_string_lossy().to_string();
+		let old = ArchiveFile {
+			path: dir.join("archive-2025-12.txt.gz"),
+			period: Some(String::from("2025-12")),
+			compressed: true,
+		};
+
+		fs::write(&filename, "x legacy\n").unwrap();
+		write_file(&old, "x december\nx christmas\n", Format::default()).unwrap();
+		fs::write(dir.join("archive-2026-01.txt"), "x january\n").unwrap();
+		fs::write(dir.join("archive-notes.txt"), "not a period\n").unwrap();
+
+		let mut archive = Archive::open(&filename).unwrap();
+
+		assert_eq!(
+			archive.text(),
+			"x legacy\nx december\nx christmas\nx january\n"
+		);
+
+		archive
+			.replace(lines(&["x legacy", "x december", "x new"]))
+			.unwrap();
+
+		assert_eq!(read_file(&old).unwrap().text, "x december\n");
+		assert!(!dir.join("archive-2026-01.txt").exists());
+		assert_eq!(
+			Archive::open(&filename).unwrap().text(),
+			"x legacy\nx new\nx december\n"
+		);
+
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
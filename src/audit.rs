@@ -0,0 +1,282 @@
+/// Append-only log of every change made to a todo, for `history`
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::io::prelude::Write;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+use crate::cfg::get_audit_log_filename;
+use crate::journal::{Change, JournalFile};
+use crate::todo::Todo;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// What happened to a todo
+pub enum Action {
+	Created,
+	Completed,
+	Reopened,
+	Reprioritized,
+	Tagged,
+	Clocked,
+	Changed,
+	Archived,
+	Unarchived,
+	Removed,
+}
+
+impl Action {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Action::Created => "created",
+			Action::Completed => "completed",
+			Action::Reopened => "reopened",
+			Action::Reprioritized => "reprioritized",
+			Action::Tagged => "tagged",
+			Action::Clocked => "clocked",
+			Action::Changed => "changed",
+			Action::Archived => "archived",
+			Action::Unarchived => "unarchived",
+			Action::Removed => "removed",
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// One line of the audit log
+pub struct Event {
+	pub time: DateTime<Local>,
+
+	/// Command line that made the change
+	pub command: String,
+
+	pub action: Action,
+
+	pub file: JournalFile,
+
+	/// Id of the todo, if its line has one
+	pub id: Option<Uuid>,
+
+	/// Line before the change, None if it was added
+	pub before: Option<String>,
+
+	/// Line after the change, None if it was removed
+	pub after: Option<String>,
+}
+
+lazy_static! {
+	/// Todos archived or unarchived by this process. Their removal from
+	/// or addition to todo.txt is part of the move, not an event of its
+	/// own.
+	static ref MOVED: Mutex<HashSet<Uuid>> = Mutex::new(HashSet::new());
+
+	/// Events of this process, whether the audit log is on or not
+	static ref LOGGED: Mutex<Vec<Event>> = Mutex::new(Vec::new());
+}
+
+/// The most telling action a modification of `before` into `after`
+/// stands for
+fn classify_modification(before: &str, after: &str) -> Action {
+	let (b, a) = match (before.parse::<Todo>(), after.parse::<Todo>()) {
+		(Ok(b), Ok(a)) => (b, a),
+		_ => return Action::Changed,
+	};
+	let clock = |t: &Todo| (t.key_value("clock").map(String::from), t.clocked().ok());
+
+	if !b.is_complete && a.is_complete {
+		Action::Completed
+	} else if b.is_complete && !a.is_complete {
+		Action::Reopened
+	} else if b.priority != a.priority {
+		Action::Reprioritized
+	} else if b.tags() != a.tags() {
+		Action::Tagged
+	} else if clock(&b) != clock(&a) {
+		Action::Clocked
+	} else {
+		Action::Changed
+	}
+}
+
+/// Action `change` stands for
+pub fn classify(change: &Change) -> Action {
+	match (change.file, &change.before, &change.after) {
+		(JournalFile::Archive, None, _) => Action::Archived,
+		(JournalFile::Archive, _, None) => Action::Unarchived,
+		(JournalFile::Todo, None, _) => Action::Created,
+		(JournalFile::Todo, _, None) => Action::Removed,
+		(_, Some(before), Some(after)) => classify_modification(before, after),
+	}
+}
+
+/// Event for a todo changed as it was moved between todo.txt and the
+/// archive, such as one reopened by `unarchive --reopen`. `change` is
+/// its removal from or addition to todo.txt, `archived` its line in the
+/// archive.
+fn moved_modification(change: &Change, archived: &str) -> Option<Event> {
+	let (before, after) = match (&change.before, &change.after) {
+		(Some(before), None) => (before.clone(), archived.to_string()),
+		(None, Some(after)) => (archived.to_string(), after.clone()),
+		_ => return None,
+	};
+
+	match classify_modification(&before, &after) {
+		_ if before == after => None,
+		Action::Changed => None,
+		action => Some(Event {
+			time: Local::now(),
+			command: change.command.clone(),
+			action,
+			file: change.file,
+			id: change.id,
+			before: Some(before),
+			after: Some(after),
+		}),
+	}
+}
+
+/// Append an event for each of `changes` to the audit log, unless it
+/// is turned off
+pub fn log(changes: &[Change]) -> Result<(), io::Error> {
+	let mut moved = MOVED.lock().unwrap_or_else(|e| e.into_inner());
+	let mut events = Vec::new();
+	let archived: HashMap<Uuid, &String> = changes
+		.iter()
+		.filter(|c| c.file == JournalFile::Archive)
+		.filter_map(|c| Some((c.id?, c.after.as_ref().or(c.before.as_ref())?)))
+		.collect();
+
+	for c in changes {
+		let action = classify(c);
+
+		match (action, c.id) {
+			(Action::Archived | Action::Unarchived, Some(id)) => {
+				moved.insert(id);
+			}
+			(Action::Removed | Action::Created, Some(id)) if moved.contains(&id) => {
+				events.extend(archived.get(&id).and_then(|a| moved_modification(c, a)));
+				continue;
+			}
+			_ => {}
+		}
+
+		events.push(Event {
+			time: Local::now(),
+			command: c.command.clone(),
+			action,
+			file: c.file,
+			id: c.id,
+			before: c.before.clone(),
+			after: c.after.clone(),
+		});
+	}
+
+	let mut content = String::new();
+
+	for event in &events {
+		content.push_str(&serde_json::to_string(event)?);
+		content.push('\n');
+	}
+
+	LOGGED
+		.lock()
+		.unwrap_or_else(|e| e.into_inner())
+		.extend(events);
+
+	let filename = match get_audit_log_filename() {
+		Some(f) if !content.is_empty() => f,
+		_ => return Ok(()),
+	};
+
+	let mut f = OpenOptions::new()
+		.append(true)
+		.create(true)
+		.open(filename)?;
+
+	f.write_all(content.as_bytes())
+}
+
+/// Events logged by this process so far, oldest first
+pub fn logged() -> Vec<Event> {
+	LOGGED.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Every event of the audit log, oldest first
+pub fn read_events() -> Result<Vec<Event>, io::Error> {
+	let filename = match get_audit_log_filename() {
+		None => return Ok(vec![]),
+		Some(f) => f,
+	};
+	let text = match fs::read_to_string(filename) {
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+		Err(e) => return Err(e),
+		Ok(text) => text,
+	};
+
+	text.lines()
+		.filter(|l| !l.trim().is_empty())
+		.map(|l| serde_json::from_str(l).map_err(io::Error::from))
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::journal::diff;
+
+	const ID: &str = "id:2a6a3d2e-4c0a-4c6e-9d3b-7a1f3b8d7c11";
+
+	fn action(file: JournalFile, before: &str, after: &str) -> Action {
+		classify(&diff(file, before, after)[0])
+	}
+
+	#[test]
+	fn classify_changes() {
+		let line = format!("call mom {}", ID);
+		let todo = JournalFile::Todo;
+
+		assert_eq!(action(todo, "", &line), Action::Created);
+		assert_eq!(action(todo, &line, ""), Action::Removed);
+		assert_eq!(action(JournalFile::Archive, "", &line), Action::Archived);
+		assert_eq!(
+			action(todo, &line, &format!("x {}", line)),
+			Action::Completed
+		);
+		assert_eq!(
+			action(todo, &format!("x {}", line), &line),
+			Action::Reopened
+		);
+		assert_eq!(
+			action(todo, &line, &format!("(A) {}", line)),
+			Action::Reprioritized
+		);
+		assert_eq!(
+			action(todo, &line, &format!("{} #home", line)),
+			Action::Tagged
+		);
+		assert_eq!(
+			action(todo, &line, &format!("{} clock:1", line)),
+			Action::Clocked
+		);
+		assert_eq!(
+			action(todo, &line, &format!("{} +Family", line)),
+			Action::Changed
+		);
+	}
+
+	#[test]
+	fn reopened_while_unarchived() {
+		let line = format!("call mom {}", ID);
+		let archived = format!("x 2021-12-25 {}", line);
+		let created = &diff(JournalFile::Todo, "", &line)[0];
+		let event = moved_modification(created, &archived).unwrap();
+
+		assert_eq!(event.action, Action::Reopened);
+		assert_eq!(event.before, Some(archived.clone()));
+		assert_eq!(event.after, Some(line));
+		assert!(moved_modification(created, &created.after.clone().unwrap()).is_none());
+	}
+}
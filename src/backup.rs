@@ -0,0 +1,245 @@
+/// Rotating snapshots of todo.txt and archive.txt, taken before a
+/// command changes them
+use chrono::{Local, NaiveDateTime};
+use std::fs;
+use std::io;
+use std::io::prelude::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::archive::Archive;
+use crate::cfg::{get_archive_filename, get_backup_count, get_backup_path, get_todo_filename};
+use crate::journal::JournalFile;
+use crate::todo_file::{write_default_text, write_file_atomically};
+
+/// Timestamp appended to the name of a snapshot, sorts by time
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%.6f";
+
+/// Set once the default files were snapshotted by this process
+static DEFAULT_FILES_BACKED_UP: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Debug)]
+/// A snapshot of a file
+pub struct Backup {
+	pub path: PathBuf,
+
+	/// Snapshot of the archive taken along with todo.txt, None for
+	/// other files and snapshots taken without one
+	pub archive_path: Option<PathBuf>,
+
+	/// Local time the snapshot was taken
+	pub taken_at: NaiveDateTime,
+}
+
+fn file_name(filename: &str) -> String {
+	Path::new(filename)
+		.file_name()
+		.map(|n| n.to_string_lossy().to_string())
+		.unwrap_or_default()
+}
+
+/// Snapshots of `filename` kept in `dir`, newest first
+pub fn list_backups(filename: &str, dir: &str) -> Result<Vec<Backup>, io::Error> {
+	let prefix = format!("{}.", file_name(filename));
+	let entries = match fs::read_dir(dir) {
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+		Err(e) => return Err(e),
+		Ok(entries) => entries,
+	};
+	let mut backups = Vec::new();
+
+	for entry in entries {
+		let entry = entry?;
+		let name = entry.file_name().to_string_lossy().to_string();
+		let timestamp = match name.strip_prefix(&prefix) {
+			None => continue,
+			Some(t) => t,
+		};
+
+		if let Ok(taken_at) = NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT) {
+			backups.push(Backup {
+				path: entry.path(),
+				archive_path: None,
+				taken_at,
+			});
+		}
+	}
+
+	backups.sort_by_key(|b| std::cmp::Reverse(b.taken_at));
+
+	Ok(backups)
+}
+
+/// Copy `filename` into `dir`, then remove its oldest snapshots so that
+/// no more than `count` are kept. Nothing is copied if `count` is 0 or
+/// `filename` does not exist yet.
+pub fn backup_file(filename: &str, dir: &str, count: usize) -> Result<Option<Backup>, io::Error> {
+	if count == 0 || !Path::new(filename).exists() {
+		return Ok(None);
+	}
+
+	fs::create_dir_all(dir)?;
+
+	let taken_at = Local::now().naive_local();
+	let path = snapshot_path(filename, dir, taken_at);
+
+	fs::copy(filename, &path)?;
+
+	for old in list_backups(filename, dir)?.iter().skip(count) {
+		fs::remove_file(&old.path)?;
+	}
+
+	Ok(Some(Backup {
+		path,
+		archive_path: None,
+		taken_at,
+	}))
+}
+
+/// Path in `dir` of the snapshot of `filename` taken at `taken_at`
+fn snapshot_path(filename: &str, dir: &str, taken_at: NaiveDateTime) -> PathBuf {
+	Path::new(dir).join(format!(
+		"{}.{}",
+		file_name(filename),
+		taken_at.format(TIMESTAMP_FORMAT)
+	))
+}
+
+/// Replace `filename` with the content of `backup`
+pub fn restore_backup(backup: &Backup, filename: &str) -> Result<(), io::Error> {
+	let content = fs::read(&backup.path)?;
+
+	write_file_atomically(filename, |w| w.write_all(&content))
+}
+
+/// Snapshot `filename` as `backup_file` does, so that the restore can be
+/// undone, then replace it with the content of `backup`. The content is
+/// read first: `backup` may be the oldest snapshot, removed to make room
+/// for the new one.
+pub fn restore_file(
+	backup: &Backup,
+	filename: &str,
+	dir: &str,
+	count: usize,
+) -> Result<(), io::Error> {
+	let content = fs::read(&backup.path)?;
+
+	backup_file(filename, dir, count)?;
+	write_file_atomically(filename, |w| w.write_all(&content))
+}
+
+/// Snapshot the user's default todo.txt and archive.txt files, as
+/// configured by `backup_count` and `backup_path`
+///
+/// Both snapshots get the same time. The archive is snapshotted as one
+/// file with the lines of all the files it was rotated into. Only the
+/// first call of a process takes them, so a command changing both files
+/// is snapshotted once, before its first change. Nothing is copied if
+/// todo.txt does not exist yet.
+pub fn backup_default_files() -> Result<Option<Backup>, io::Error> {
+	if DEFAULT_FILES_BACKED_UP.swap(true, Ordering::SeqCst) {
+		return Ok(None);
+	}
+
+	let filename = get_todo_filename();
+	let archive_filename = get_archive_filename();
+	let dir = get_backup_path();
+	let mut backup = match backup_file(&filename, &dir, get_backup_count())? {
+		Some(b) => b,
+		None => return Ok(None),
+	};
+	let archive_path = snapshot_path(&archive_filename, &dir, backup.taken_at);
+
+	fs::write(&archive_path, Archive::open(&archive_filename)?.text())?;
+	backup.archive_path = Some(archive_path);
+
+	// Archive snapshots go with the todo.txt snapshots still kept
+	let kept: Vec<NaiveDateTime> = list_backups(&filename, &dir)?
+		.iter()
+		.map(|b| b.taken_at)
+		.collect();
+
+	for old in list_backups(&archive_filename, &dir)? {
+		if !kept.contains(&old.taken_at) {
+			fs::remove_file(&old.path)?;
+		}
+	}
+
+	Ok(Some(backup))
+}
+
+/// Restore the user's default todo.txt and archive.txt files from
+/// `backup`, after snapshotting them so that the restore can be undone
+/// (see `restore_file`). The archive is left as it is if `backup` has no
+/// snapshot of it. Its lines stay in the rotated files they are in, see
+/// `archive::Archive::replace`.
+pub fn restore_default_files(backup: &Backup) -> Result<(), io::Error> {
+	let content = fs::read(&backup.path)?;
+	let archive = match &backup.archive_path {
+		Some(path) => Some(fs::read_to_string(path)?),
+		None => None,
+	};
+
+	backup_default_files()?;
+	write_file_atomically(&get_todo_filename(), |w| w.write_all(&content))?;
+
+	match archive {
+		Some(text) => write_default_text(JournalFile::Archive, &text),
+		None => Ok(()),
+	}
+}
+
+/// Snapshots of the user's default todo.txt file, newest first, with
+/// the snapshot of the archive taken along with each
+pub fn list_default_backups() -> Result<Vec<Backup>, io::Error> {
+	let dir = get_backup_path();
+	let archives = list_backups(&get_archive_filename(), &dir)?;
+	let mut backups = list_backups(&get_todo_filename(), &dir)?;
+
+	for b in &mut backups {
+		b.archive_path = archives
+			.iter()
+			.find(|a| a.taken_at == b.taken_at)
+			.map(|a| a.path.clone());
+	}
+
+	Ok(backups)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_util::scratch_dir;
+
+	#[test]
+	fn backups_rotate_and_restore() {
+		let dir = scratch_dir("backups");
+		let filename = dir.join("todo.txt").to_str().unwrap().to_string();
+		let backup_dir = dir.join("backups").to_str().unwrap().to_string();
+
+		assert!(backup_file(&filename, &backup_dir, 2).unwrap().is_none());
+
+		for content in ["one\n", "two\n", "three\n"] {
+			fs::write(&filename, content).unwrap();
+			backup_file(&filename, &backup_dir, 2).unwrap();
+		}
+
+		let backups = list_backups(&filename, &backup_dir).unwrap();
+		assert_eq!(backups.len(), 2);
+		assert_eq!(fs::read_to_string(&backups[0].path).unwrap(), "three\n");
+		assert_eq!(fs::read_to_string(&backups[1].path).unwrap(), "two\n");
+
+		restore_backup(&backups[1], &filename).unwrap();
+		assert_eq!(fs::read_to_string(&filename).unwrap(), "two\n");
+
+		// The oldest snapshot is rotated out by the one taken first
+		fs::write(&filename, "four\n").unwrap();
+		restore_file(&backups[1], &filename, &backup_dir, 2).unwrap();
+		assert_eq!(fs::read_to_string(&filename).unwrap(), "two\n");
+
+		let backups = list_backups(&filename, &backup_dir).unwrap();
+		assert_eq!(fs::read_to_string(&backups[0].path).unwrap(), "four\n");
+
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
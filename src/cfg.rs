@@ -3,10 +3,57 @@ use std::collections::HashMap;
 use std::error::Error;
 use std::path::PathBuf;
 use std::sync::RwLock;
+use std::time::Duration;
+
+/// How todos are identified in todo.txt
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IdStrategy {
+	/// A full UUID is written under the given key
+	Uuid(String),
+
+	/// The first 8 characters of a UUID are written under the given key
+	Short(String),
+
+	/// Nothing is written, todos are identified by a hash of their line
+	None,
+}
+
+impl IdStrategy {
+	/// Key the id is written under, if any
+	pub fn key(&self) -> Option<&str> {
+		match self {
+			IdStrategy::Uuid(key) | IdStrategy::Short(key) => Some(key),
+			IdStrategy::None => None,
+		}
+	}
+}
+
+impl Default for IdStrategy {
+	fn default() -> Self {
+		IdStrategy::Uuid(String::from("id"))
+	}
+}
+
+/// How archive.txt is split into one file per period
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveRotation {
+	/// Everything is archived to archive.txt
+	None,
+
+	/// archive-2026-10.txt, ...
+	Monthly,
+
+	/// archive-2026.txt, ...
+	Yearly,
+}
 
 lazy_static! {
 	static ref SETTINGS: RwLock<Config> = RwLock::new(Config::default());
 	static ref CONFIG_FILE: Option<PathBuf> = find_config_file();
+
+	/// The configuration file read, found or given with `--config`
+	static ref READ_CONFIG_FILE: RwLock<Option<PathBuf>> = RwLock::new(None);
+	static ref LIST: RwLock<Option<String>> = RwLock::new(None);
 }
 
 fn find_config_file() -> Option<PathBuf> {
@@ -38,11 +85,7 @@ fn find_config_file() -> Option<PathBuf> {
 	// Look for a XDG_CONFIG_PATH/todo-txt/config.toml file
 	//
 
-	let mut filename = dirs::config_dir().unwrap();
-	filename.push("todo-txt");
-	filename.push("config.toml");
-
-	let std_config_file = filename.to_path_buf();
+	let std_config_file = get_default_config_filename();
 
 	if std_config_file.exists() {
 		return Some(std_config_file);
@@ -51,6 +94,16 @@ fn find_config_file() -> Option<PathBuf> {
 	None
 }
 
+/// XDG_CONFIG_PATH/todo-txt/config.toml, used when no .todo-txt.toml is
+/// found in the current directory or its parents
+pub fn get_default_config_filename() -> PathBuf {
+	let mut filename = dirs::config_dir().unwrap();
+	filename.push("todo-txt");
+	filename.push("config.toml");
+
+	filename
+}
+
 pub fn read_config(config_filename: Option<&str>) -> Result<(), Box<dyn Error>> {
 	let real_filename = match config_filename {
 		Some(filename) => filename.to_string(),
@@ -71,15 +124,14 @@ pub fn read_config(config_filename: Option<&str>) -> Result<(), Box<dyn Error>>
 		.write()?
 		.merge(config::File::with_name(&real_filename))?;
 
+	*READ_CONFIG_FILE.write()? = Some(PathBuf::from(real_filename));
+
 	Ok(())
 }
 
 fn get_bool(name: &str) -> bool {
 	match SETTINGS.read() {
-		Ok(settings) => match settings.get_bool(name) {
-			Ok(value) => value,
-			_ => false,
-		},
+		Ok(settings) => settings.get_bool(name).unwrap_or_default(),
 		_ => false,
 	}
 }
@@ -96,10 +148,7 @@ fn get_char(name: &str) -> Option<char> {
 
 fn get_string(name: &str) -> Option<String> {
 	match SETTINGS.read() {
-		Ok(settings) => match settings.get_str(name) {
-			Ok(value) => Some(value),
-			_ => None,
-		},
+		Ok(settings) => settings.get_str(name).ok(),
 		_ => None,
 	}
 }
@@ -118,7 +167,7 @@ fn get_data_path() -> PathBuf {
 
 	let data_pathbuf = match SETTINGS.read() {
 		Ok(settings) => match settings.get::<String>("data_path") {
-			Ok(setting) => PathBuf::from(setting),
+			Ok(setting) => relative_to_config_file(PathBuf::from(setting)),
 			Err(_) => get_default_data_path(),
 		},
 		_ => get_default_data_path(),
@@ -142,10 +191,15 @@ fn get_data_path() -> PathBuf {
 }
 
 fn relative_to_config_file(pb: PathBuf) -> PathBuf {
+	let config_file = match READ_CONFIG_FILE.read() {
+		Ok(f) => f.clone(),
+		_ => None,
+	};
+
 	match pb.is_relative() {
-		true => match CONFIG_FILE.as_ref() {
+		true => match config_file.as_ref().and_then(|p| p.parent()) {
 			None => pb,
-			Some(p) => p.parent().unwrap().join(pb),
+			Some(dir) => dir.join(pb),
 		},
 		false => pb,
 	}
@@ -153,7 +207,7 @@ fn relative_to_config_file(pb: PathBuf) -> PathBuf {
 
 /// Get the named file under the defined data directory.
 pub fn get_data_filename(filename: &str) -> String {
-	let mut pb = relative_to_config_file(get_data_path());
+	let mut pb = get_data_path();
 	pb.push(filename);
 	pb.to_str().unwrap().to_string()
 }
@@ -173,20 +227,157 @@ fn get_filename(filename_key: &str, default_filename: &str) -> String {
 	}
 }
 
+/// true if `name` is a list of the `[lists]` table
+fn is_configured_list(name: &str) -> bool {
+	match SETTINGS.read() {
+		Ok(settings) => settings.get_table(&format!("lists.{}", name)).is_ok(),
+		_ => false,
+	}
+}
+
+/// Names of the lists in the `[lists]` table, sorted. `default` stands
+/// for the top level `todo_filename` and `archive_filename`, unless a
+/// list is named that.
+pub fn get_list_names() -> Vec<String> {
+	let mut names: Vec<String> = match SETTINGS.read() {
+		Ok(settings) => settings
+			.get_table("lists")
+			.map(|t| t.into_keys().collect())
+			.unwrap_or_default(),
+		_ => vec![],
+	};
+
+	names.sort();
+
+	if !names.iter().any(|n| n == "default") {
+		names.insert(0, String::from("default"));
+	}
+
+	names
+}
+
+/// Work on the list `name` instead of the default one
+pub fn set_list(name: &str) -> Result<(), String> {
+	if name != "default" && !is_configured_list(name) {
+		return Err(format!("unknown list '{}'", name));
+	}
+
+	if let Ok(mut list) = LIST.write() {
+		*list = Some(name.to_string());
+	}
+
+	Ok(())
+}
+
+/// Check that `default_list`, if set, names a list of the `[lists]`
+/// table or `default`
+pub fn check_default_list() -> Result<(), String> {
+	match get_string("default_list") {
+		Some(name) if name != "default" && !is_configured_list(&name) => {
+			Err(format!("unknown default_list '{}'", name))
+		}
+		_ => Ok(()),
+	}
+}
+
+/// Name of the list commands work on, the one given to `set_list` or
+/// else `default_list`. None for the top level files.
+pub fn get_list() -> Option<String> {
+	let name = LIST
+		.read()
+		.ok()
+		.and_then(|l| l.clone())
+		.or_else(|| get_string("default_list"));
+
+	name.filter(|n| is_configured_list(n))
+}
+
+/// todo.txt of the list `name`. `lists.<name>.todo_filename`, by default
+/// `<name>.txt` in the data path.
+pub fn get_list_todo_filename(name: &str) -> String {
+	match is_configured_list(name) {
+		true => get_filename(
+			&format!("lists.{}.todo_filename", name),
+			&format!("{}.txt", name),
+		),
+		false => get_filename("todo_filename", "todo.txt"),
+	}
+}
+
+/// archive.txt of the list `name`. `lists.<name>.archive_filename`, by
+/// default `<name>.archive.txt` in the data path.
+pub fn get_list_archive_filename(name: &str) -> String {
+	match is_configured_list(name) {
+		true => get_filename(
+			&format!("lists.{}.archive_filename", name),
+			&format!("{}.archive.txt", name),
+		),
+		false => get_filename("archive_filename", "archive.txt"),
+	}
+}
+
 pub fn get_todo_filename() -> String {
-	get_filename("todo_filename", "todo.txt")
+	get_list_todo_filename(&get_list().unwrap_or_default())
 }
 
 pub fn get_archive_filename() -> String {
-	get_filename("archive_filename", "archive.txt")
+	get_list_archive_filename(&get_list().unwrap_or_default())
+}
+
+/// Name of a file kept in the data path for the current list, such as
+/// the journal. Named lists prefix `filename` with their name.
+pub fn get_list_data_filename(filename: &str) -> String {
+	match get_list() {
+		Some(name) => get_data_filename(&format!("{}.{}", name, filename)),
+		None => get_data_filename(filename),
+	}
+}
+
+/// File the audit log is appended to, `audit.jsonl` under the data
+/// path by default. None if `audit_log` is set to an empty string.
+pub fn get_audit_log_filename() -> Option<String> {
+	match get_string("audit_log") {
+		Some(v) if v.is_empty() => None,
+		_ => Some(get_filename("audit_log", "audit.jsonl")),
+	}
+}
+
+/// How archive.txt is rotated, `archive_rotation` of "monthly" or
+/// "yearly". Not rotated by default.
+pub fn get_archive_rotation() -> ArchiveRotation {
+	match get_string("archive_rotation").as_deref() {
+		Some("monthly") => ArchiveRotation::Monthly,
+		Some("yearly") => ArchiveRotation::Yearly,
+		_ => ArchiveRotation::None,
+	}
+}
+
+/// true if the archive files of past periods are gzipped
+pub fn get_archive_compress() -> bool {
+	get_bool("archive_compress")
+}
+
+/// true if the directory of todo.txt is kept as a git repository, with
+/// a commit for every command that changes a todo
+pub fn get_git_storage() -> bool {
+	get_bool("git_storage")
+}
+
+/// Directory snapshots of todo.txt are kept in
+pub fn get_backup_path() -> String {
+	let path = get_filename("backup_path", "backups");
+
+	match get_list() {
+		Some(name) => PathBuf::from(path).join(name).to_str().unwrap().to_string(),
+		None => path,
+	}
 }
 
 pub fn get_mutually_exclusive_tags() -> Vec<Vec<String>> {
 	match SETTINGS.read() {
-		Ok(settings) => match settings.get::<Vec<Vec<String>>>("mutually_exclusive_tags") {
-			Ok(v) => v,
-			Err(_) => vec![],
-		},
+		Ok(settings) => settings
+			.get::<Vec<Vec<String>>>("mutually_exclusive_tags")
+			.unwrap_or_default(),
 		_ => vec![],
 	}
 }
@@ -195,10 +386,9 @@ pub fn get_project_rules(project_name: &str) -> HashMap<String, String> {
 	let key = format!("project_rules.{}", project_name);
 
 	match SETTINGS.read() {
-		Ok(settings) => match settings.get::<HashMap<String, String>>(&key) {
-			Ok(hm) => hm,
-			_ => HashMap::new(),
-		},
+		Ok(settings) => settings
+			.get::<HashMap<String, String>>(&key)
+			.unwrap_or_default(),
 		_ => HashMap::new(),
 	}
 }
@@ -229,3 +419,47 @@ pub fn get_note_file_extension() -> String {
 		Some(v) => v,
 	}
 }
+
+pub fn get_id_strategy() -> IdStrategy {
+	let key = get_string("id_key").unwrap_or_else(|| String::from("id"));
+
+	match get_string("id_strategy").as_deref() {
+		Some("none") => IdStrategy::None,
+		Some("short") => IdStrategy::Short(key),
+		_ => IdStrategy::Uuid(key),
+	}
+}
+
+/// How long to wait for another todo-txt command to release the todo
+/// file, 10 seconds by default
+pub fn get_lock_timeout() -> Duration {
+	let seconds = match SETTINGS.read() {
+		Ok(settings) => settings.get_float("lock_timeout").unwrap_or(10.0),
+		_ => 10.0,
+	};
+
+	Duration::try_from_secs_f64(seconds.max(0.0)).unwrap_or_else(|_| {
+		eprintln!(
+			"warning: lock_timeout = {} is out of range, waiting 10 seconds",
+			seconds
+		);
+		Duration::from_secs(10)
+	})
+}
+
+/// Number of todo.txt snapshots to keep, 10 by default. 0 disables them.
+pub fn get_backup_count() -> usize {
+	match SETTINGS.read() {
+		Ok(settings) => settings.get::<usize>("backup_count").unwrap_or(10),
+		_ => 10,
+	}
+}
+
+/// Number of operations kept in the journal for undo, 100 by default.
+/// 0 turns the journal off.
+pub fn get_journal_size() -> usize {
+	match SETTINGS.read() {
+		Ok(settings) => settings.get::<usize>("journal_size").unwrap_or(100),
+		_ => 100,
+	}
+}
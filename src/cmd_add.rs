@@ -1,10 +1,13 @@
-use crate::cfg::{get_default_priority, get_log_create_date, get_project_rules};
-use crate::repeat_time;
-use crate::todo::Todo;
-use crate::todo_file::{append_todo_to_default_file, last_inserted_todo_number};
 use chrono::{Duration, Local, NaiveDate};
 use gumdrop::Options;
 use regex::Regex;
+use todo_txt::cfg::{get_default_priority, get_log_create_date, get_project_rules};
+use todo_txt::repeat_time;
+use todo_txt::todo::Todo;
+use todo_txt::todo_file::{
+	append_todo_to_default_file, last_inserted_todo_number, lock_default_file,
+};
+use uuid::Uuid;
 
 lazy_static! {
 	static ref YYYYMMDD_RE: Regex =
@@ -74,6 +77,11 @@ pub fn execute(opts: &Opts) {
 
 	let mut t = task.parse::<Todo>().unwrap();
 
+	// A new todo, not the one an identical line without an id reads as
+	if t.id_text().is_some() {
+		t.id = Uuid::new_v4();
+	}
+
 	if get_log_create_date() {
 		t.created_at = Some(Local::today().naive_local());
 	}
@@ -82,23 +90,30 @@ pub fn execute(opts: &Opts) {
 		t.clock_in();
 	}
 
-	if t.key_values.contains_key("due") {
-		let due_str = t.key_values.get("due").unwrap().as_str();
-		let due_date = compute_relative_date(due_str);
+	// due: and t: accept relative dates, such as tomorrow or 2w
+	for key in ["due", "t"] {
+		if let Some(value) = t.key_value(key) {
+			let date = compute_relative_date(value);
 
-		t.key_values
-			.insert("due".to_string(), due_date.format("%Y-%m-%d").to_string());
+			t.set_key_value(key, &date.format("%Y-%m-%d").to_string());
+		}
 	}
 
-	for project in &t.projects {
-		let project_name = project.replace("+", "");
+	let projects: Vec<String> = t.projects().iter().map(|p| p.replace('+', "")).collect();
+
+	for project_name in projects {
 		let project_rules = get_project_rules(&project_name);
 
 		if let Some(append) = project_rules.get("append") {
-			t.task = format!("{} {}", t.task, append)
+			t.push_text(append);
 		}
 	}
 
+	let _lock = lock_default_file().unwrap_or_else(|e| {
+		eprintln!("{}", e);
+		std::process::exit(1);
+	});
+
 	append_todo_to_default_file(&t).unwrap();
 
 	if !opts.quiet {
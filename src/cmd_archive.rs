@@ -1,7 +1,8 @@
-use crate::todo_file::{
-	append_todos_to_archive_file, parse_todos_from_default_file, write_todos_to_default_file,
-};
 use gumdrop::Options;
+use todo_txt::todo_file::{
+	append_todos_to_archive_file, lock_default_file_for_rewrite, parse_todos_from_default_file,
+	write_todos_to_default_file,
+};
 
 #[derive(Debug, Options)]
 pub struct Opts {
@@ -10,19 +11,16 @@ pub struct Opts {
 }
 
 pub fn execute(_opts: &Opts) {
+	let _lock = lock_default_file_for_rewrite().unwrap_or_else(|e| {
+		eprintln!("{}", e);
+		std::process::exit(1);
+	});
 	let todo_list =
 		parse_todos_from_default_file().expect("Could not parse todos from default file");
-	let todos = todo_list.items;
-	let mut keep_todos = Vec::new();
-	let mut archive_todos = Vec::new();
-
-	for t in todos {
-		match t.is_complete {
-			false => keep_todos.push(t),
-			true => archive_todos.push(t),
-		}
-	}
+	let (keep_todos, archive_todos) = todo_list.split();
 
+	// Archive first, so nothing is lost if writing todo.txt fails
+	append_todos_to_archive_file(&archive_todos.items)
+		.expect("Could not write todos to archive file");
 	write_todos_to_default_file(&keep_todos).expect("Could not write todos to default file");
-	append_todos_to_archive_file(&archive_todos).expect("Could not write todos to default file");
 }
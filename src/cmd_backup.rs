@@ -0,0 +1,124 @@
+use todo_txt::backup::{list_default_backups, restore_default_files};
+use todo_txt::encoding;
+use todo_txt::journal::{diff, record, JournalFile};
+use todo_txt::todo_file::{lock_default_file, read_default_text};
+
+use gumdrop::Options;
+
+#[derive(Debug, Options)]
+pub struct Opts {
+	#[options(help = "Print help message")]
+	help: bool,
+
+	#[options(command)]
+	command: Option<BackupCommand>,
+}
+
+#[derive(Debug, Options)]
+enum BackupCommand {
+	#[options(help = "List snapshots of todo.txt and archive.txt, newest first")]
+	List(ListOpts),
+
+	#[options(help = "Replace todo.txt and archive.txt with snapshot #")]
+	Restore(RestoreOpts),
+}
+
+#[derive(Debug, Options)]
+struct ListOpts {
+	#[options(help = "Print help message")]
+	help: bool,
+}
+
+#[derive(Debug, Options)]
+struct RestoreOpts {
+	#[options(help = "Print help message")]
+	help: bool,
+
+	#[options(free, help = "Snapshot number, as shown by backup list")]
+	number: Option<usize>,
+}
+
+fn list() {
+	let backups = list_default_backups().expect("Could not list backups");
+
+	if backups.is_empty() {
+		println!("No backups");
+	}
+
+	for (i, b) in backups.iter().enumerate() {
+		let todo_count = encoding::read_file(&b.path)
+			.map(|d| d.text.lines().filter(|l| !l.trim().is_empty()).count())
+			.unwrap_or(0);
+
+		println!(
+			"  {:3}: {}  {} todos",
+			i + 1,
+			b.taken_at.format("%Y-%m-%d %H:%M:%S"),
+			todo_count
+		);
+	}
+}
+
+fn restore(opts: &RestoreOpts) {
+	let _lock = lock_default_file().unwrap_or_else(|e| {
+		eprintln!("{}", e);
+		std::process::exit(1);
+	});
+	let number = opts.number.unwrap_or_else(|| {
+		eprintln!("no backup number given, see backup list");
+		std::process::exit(1);
+	});
+	let backups = list_default_backups().unwrap_or_else(|e| {
+		eprintln!("could not list backups: {}", e);
+		std::process::exit(1);
+	});
+	let backup = match backups.get(number.wrapping_sub(1)) {
+		Some(b) => b.clone(),
+		None => {
+			eprintln!("no backup #{}, see backup list", number);
+			std::process::exit(1);
+		}
+	};
+
+	let files = [JournalFile::Todo, JournalFile::Archive];
+	let before: Vec<String> = files
+		.iter()
+		.map(|f| read_default_text(*f).unwrap_or_default())
+		.collect();
+
+	restore_default_files(&backup).unwrap_or_else(|e| {
+		eprintln!("could not restore backup #{}: {}", number, e);
+		std::process::exit(1);
+	});
+
+	let mut changes = Vec::new();
+
+	for (file, before) in files.iter().zip(&before) {
+		let after = read_default_text(*file).unwrap_or_default();
+
+		changes.extend(diff(*file, before, &after));
+	}
+
+	record(changes).unwrap_or_else(|e| {
+		eprintln!("could not write journal: {}", e);
+		std::process::exit(1);
+	});
+
+	let restored = match backup.archive_path {
+		Some(_) => "todo.txt and archive.txt",
+		None => "todo.txt",
+	};
+
+	println!(
+		"Restored {} from {}",
+		restored,
+		backup.taken_at.format("%Y-%m-%d %H:%M:%S")
+	);
+}
+
+pub fn execute(opts: &Opts) {
+	match &opts.command {
+		Some(BackupCommand::Restore(ropts)) => restore(ropts),
+		_ => list(),
+	}
+}
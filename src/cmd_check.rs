@@ -0,0 +1,45 @@
+use todo_txt::cfg::get_todo_filename;
+use todo_txt::todo_file::check_todos;
+
+use gumdrop::Options;
+use std::fs::File;
+use std::io::BufReader;
+
+#[derive(Debug, Options)]
+pub struct Opts {
+	#[options(help = "Print help message")]
+	help: bool,
+
+	#[options(free, help = "Files to check, defaults to todo.txt")]
+	free: Vec<String>,
+}
+
+pub fn execute(opts: &Opts) {
+	let filenames = if opts.free.is_empty() {
+		vec![get_todo_filename()]
+	} else {
+		opts.free.clone()
+	};
+	let mut error_count = 0;
+
+	for filename in filenames {
+		let errors = match File::open(&filename).and_then(|f| check_todos(BufReader::new(f))) {
+			Err(e) => {
+				println!("{}: {}", filename, e);
+				error_count += 1;
+				continue;
+			}
+			Ok(errors) => errors,
+		};
+
+		for e in &errors {
+			println!("{}:{}", filename, e);
+		}
+
+		error_count += errors.len();
+	}
+
+	if error_count > 0 {
+		std::process::exit(1);
+	}
+}
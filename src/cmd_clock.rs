@@ -1,8 +1,11 @@
-use crate::hms;
-use crate::todo::Todo;
-use crate::todo_file::{parse_todos_from_default_file, write_todos_to_default_file};
-
-use chrono::{Local, TimeZone};
+use todo_txt::hms;
+use todo_txt::todo::Todo;
+use todo_txt::todo_file::{
+	lock_default_file_for_rewrite, parse_todos_from_default_file, write_todos_to_default_file,
+};
+use todo_txt::todo_list::TodoList;
+
+use chrono::Local;
 use gumdrop::Options;
 
 #[derive(Debug, Options)]
@@ -10,8 +13,8 @@ pub struct Opts {
 	#[options(help = "Print help message")]
 	help: bool,
 
-	#[options(help = "Only show time for task #")]
-	only_time: u32,
+	#[options(help = "Only show time for task # or id", meta = "TODO")]
+	only_time: String,
 
 	#[options(help = "Clear the clock in state of a task")]
 	clear: bool,
@@ -27,67 +30,64 @@ pub struct Opts {
 
 	#[options(free)]
 	free: Vec<String>,
+
+	#[options(help = "Also todos matching QUERY, as ls takes it", meta = "QUERY")]
+	matching: String,
 }
 
-fn set_clocked(todos: &mut Vec<Todo>, ids: &Vec<String>, new_clock: &String) {
-	for id in ids.iter() {
-		let iid = id.parse::<usize>().unwrap();
+fn set_clocked(todos: &mut [Todo], positions: &[usize], new_clock: &str) {
+	if hms::parse(new_clock).is_none() {
+		eprintln!("invalid clocked duration '{}', expected XhXmXs", new_clock);
+		std::process::exit(1);
+	}
 
-		if let Some(t) = todos.get_mut(iid - 1) {
-			t.key_values
-				.insert("clocked".to_string(), new_clock.clone());
+	for position in positions {
+		if let Some(t) = todos.get_mut(*position) {
+			t.set_key_value("clocked", new_clock);
 		}
 	}
 }
 
-fn clear_clocked(todos: &mut Vec<Todo>, ids: &Vec<String>) {
-	for id in ids.iter() {
-		let iid = id.parse::<usize>().unwrap();
-
-		if let Some(t) = todos.get_mut(iid - 1) {
-			t.key_values.remove("clocked");
+fn clear_clocked(todos: &mut [Todo], positions: &[usize]) {
+	for position in positions {
+		if let Some(t) = todos.get_mut(*position) {
+			t.remove_key_value("clocked");
 		}
 	}
 }
 
-fn clear_clock(todos: &mut Vec<Todo>, ids: &Vec<String>) {
-	for id in ids.iter() {
-		let iid = id.parse::<usize>().unwrap();
-
-		if let Some(t) = todos.get_mut(iid - 1) {
-			t.key_values.remove("clock");
+fn clear_clock(todos: &mut [Todo], positions: &[usize]) {
+	for position in positions {
+		if let Some(t) = todos.get_mut(*position) {
+			t.remove_key_value("clock");
 		}
 	}
 }
 
-fn check_into_or_outof(todos: &mut Vec<Todo>, ids: &Vec<String>) {
-	for id in ids.iter() {
-		let iid = id.parse::<usize>().unwrap();
-
-		if let Some(t) = todos.get_mut(iid - 1) {
-			if t.has_clock() {
-				t.clock_out();
-			} else {
+fn check_into_or_outof(todos: &mut [Todo], positions: &[usize]) {
+	for position in positions {
+		if let Some(t) = todos.get_mut(*position) {
+			if !t.has_clock() {
 				t.clock_in();
+			} else if let Err(e) = t.clock_out() {
+				eprintln!("todo {}: could not clock out, {}", t.index + 1, e);
 			}
 		}
 	}
 }
 
-fn clock_out_of_all(todos: &mut Vec<Todo>) {
+fn clock_out_of_all(todos: &mut [Todo]) {
 	for t in todos.iter_mut() {
-		if t.has_clock() {
-			t.clock_out();
+		if let Err(e) = t.clock_out() {
+			eprintln!("todo {}: could not clock out, {}", t.index + 1, e);
 		}
 	}
 }
 
-fn display_only_time(todos: Vec<Todo>, index: u32) {
-	let todo = todos.iter().find(|v| v.index == index - 1);
-
-	let display = match todo {
-		None => "".to_string(),
-		Some(t) => t.elapsed_time(),
+fn display_only_time(todo_list: TodoList, selector: &str) {
+	let display = match todo_list.find(selector) {
+		Err(_) => "".to_string(),
+		Ok(position) => todo_list.items[position].elapsed_time(),
 	};
 
 	println!("{}", display);
@@ -97,53 +97,59 @@ fn display_clocked_todo_items(todos: Vec<Todo>) {
 	let now = Local::now();
 
 	for t in todos.iter() {
-		if let Some(clock) = t.key_values.get("clock") {
-			let seconds = match clock.parse::<i64>() {
-				Err(_) => 0,
-				Ok(v) => v,
-			};
-			let todo_clock_in = Local.timestamp(seconds, 0);
+		if let Ok(Some(todo_clock_in)) = t.clock_started() {
 			let time_diff = now - todo_clock_in;
 			let hms = hms::from_seconds(time_diff.num_seconds());
 
-			let clocked_time = match t.key_values.get("clocked") {
-				None => hms,
-				Some(t) => format!("{}/{}", hms, t),
+			let clocked_time = match t.clocked() {
+				Ok(clocked) if !clocked.is_zero() => {
+					format!("{}/{}", hms, hms::from_seconds(clocked.num_seconds()))
+				}
+				_ => hms,
 			};
 
-			println!("{:4}: {:10} {}", t.index + 1, clocked_time, t.task);
+			println!("{:4}: {:10} {}", t.index + 1, clocked_time, t.task());
 		}
 	}
 }
 
 pub fn execute(opts: &Opts) {
-	if opts.free.len() > 0 || opts.out_of_all {
+	if !opts.free.is_empty() || !opts.matching.is_empty() || opts.out_of_all {
+		let _lock = lock_default_file_for_rewrite().unwrap_or_else(|e| {
+			eprintln!("{}", e);
+			std::process::exit(1);
+		});
 		let todo_list =
 			&mut parse_todos_from_default_file().expect("Could not parse todos from default file");
+		let positions = todo_list
+			.select(&opts.free, &opts.matching)
+			.unwrap_or_else(|e| {
+				eprintln!("{}", e);
+				std::process::exit(1);
+			});
 		let todos = &mut todo_list.items;
 
 		if opts.clear {
-			clear_clock(todos, &opts.free);
+			clear_clock(todos, &positions);
 		} else if opts.clear_clocked {
-			clear_clocked(todos, &opts.free);
-		} else if opts.set_time.is_empty() == false {
-			set_clocked(todos, &opts.free, &opts.set_time);
+			clear_clocked(todos, &positions);
+		} else if !opts.set_time.is_empty() {
+			set_clocked(todos, &positions, &opts.set_time);
 		} else if opts.out_of_all {
 			clock_out_of_all(todos);
 		} else {
-			check_into_or_outof(todos, &opts.free);
+			check_into_or_outof(todos, &positions);
 		}
 
-		write_todos_to_default_file(&todos).expect("Could not write todos to default file");
+		write_todos_to_default_file(todo_list).expect("Could not write todos to default file");
 	} else {
 		let todo_list =
 			parse_todos_from_default_file().expect("Could not parse todos from default file");
-		let todos = todo_list.items;
 
-		if opts.only_time > 0 {
-			display_only_time(todos, opts.only_time);
+		if !opts.only_time.is_empty() {
+			display_only_time(todo_list, &opts.only_time);
 		} else {
-			display_clocked_todo_items(todos);
+			display_clocked_todo_items(todo_list.items);
 		}
 	}
 }
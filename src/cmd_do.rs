@@ -1,10 +1,11 @@
-use crate::cfg::get_auto_archive;
-use crate::cfg::get_log_complete_date;
-use crate::repeat_time::next_date;
-use crate::todo::Todo;
-use crate::todo_file::{
-	append_todo_to_archive_file, parse_todos_from_default_file, write_todos_to_default_file,
+use todo_txt::cfg::get_auto_archive;
+use todo_txt::cfg::get_log_complete_date;
+use todo_txt::todo::Todo;
+use todo_txt::todo_file::{
+	append_todo_to_archive_file, lock_default_file_for_rewrite, parse_todos_from_default_file,
+	write_todos_to_default_file,
 };
+use todo_txt::todo_list::SelectError;
 
 use chrono::Local;
 use gumdrop::Options;
@@ -18,37 +19,78 @@ pub struct Opts {
 	#[options(free)]
 	free: Vec<String>,
 
+	#[options(
+		help = "Also open todos matching QUERY, as ls takes it",
+		meta = "QUERY"
+	)]
+	matching: String,
+
 	#[options(help = "Archive todo item once markd done")]
 	archive: bool,
 }
 
 pub fn execute(opts: &Opts) {
 	let should_archive = opts.archive || get_auto_archive();
+	let _lock = lock_default_file_for_rewrite().unwrap_or_else(|e| {
+		eprintln!("{}", e);
+		std::process::exit(1);
+	});
 	let todo_list =
 		&mut parse_todos_from_default_file().expect("Could not parse todos from default file");
+	let select = |result: Result<Vec<usize>, SelectError>| {
+		result.unwrap_or_else(|e| {
+			eprintln!("{}", e);
+			std::process::exit(1);
+		})
+	};
+
+	// Todos given by number or id are toggled, but --matching only
+	// completes open todos instead of reopening the done ones it matches
+	let matched: Vec<usize> = select(todo_list.select(&[], &opts.matching))
+		.into_iter()
+		.filter(|p| !todo_list.items[*p].is_complete)
+		.collect();
+
+	if matched.is_empty() && !opts.matching.trim().is_empty() {
+		eprintln!("no open todo matches '{}'", opts.matching);
+		std::process::exit(1);
+	}
+
+	// A todo given twice, or given and matched, is toggled once
+	let mut positions = select(todo_list.find_all(&opts.free));
+
+	positions.extend(matched);
+	positions.sort();
+	positions.dedup();
+
 	let todos = &mut todo_list.items;
 
 	let mut new_todos: Vec<Todo> = [].to_vec();
 	let mut marked_ids = Vec::new();
 
-	for id in &opts.free {
-		let iid = id.parse::<usize>().unwrap();
+	for position in positions {
+		if let Some(t) = todos.get_mut(position) {
+			let number = t.index + 1;
 
-		if let Some(t) = todos.get_mut(iid - 1) {
 			t.is_complete = !t.is_complete;
 			if t.is_complete {
-				if t.has_clock() {
-					t.clock_out()
+				if let Err(e) = t.clock_out() {
+					eprintln!("todo {}: could not clock out, {}", number, e);
 				}
 
 				if get_log_complete_date() {
 					t.completed_at = Some(Local::today().naive_local());
 				}
 
+				let repeat = t.repeat().unwrap_or_else(|e| {
+					eprintln!("todo {}: not repeating, {}", number, e);
+					None
+				});
+
 				// If this is a repeating task, we always keep the
 				// original intact. So duplicate parent task, clear it's
 				// temporary state, and mark the duplicate as completed.
-				if t.has_repeat() {
+				if let Some(repeat) = repeat {
 					let mut done_t = t.clone();
 					done_t.id = Uuid::new_v4();
 
@@ -61,17 +103,11 @@ pub fn execute(opts: &Opts) {
 					t.reset(true);
 
 					// compute our new due date
-					//
-					// unwrap() -> we did check to see we have a
-					// repeat pattern above.
-					let repeat_pattern = t.key_values.get("rep").unwrap();
-
-					if let Some(v) = next_date(repeat_pattern, None) {
-						t.key_values
-							.insert("due".to_string(), v.format("%Y-%m-%d").to_string());
+					if let Some(v) = repeat.next_date(None) {
+						t.set_key_value("due", &v.format("%Y-%m-%d").to_string());
 					}
 				} else if should_archive {
-					marked_ids.push(iid - 1);
+					marked_ids.push(position);
 
 					append_todo_to_archive_file(t).unwrap();
 				}
@@ -84,6 +120,8 @@ pub fn execute(opts: &Opts) {
 	if should_archive {
 		marked_ids.sort();
 
+		marked_ids.dedup();
+
 		for index in marked_ids.iter().rev() {
 			todos.remove(*index);
 		}
@@ -91,5 +129,5 @@ pub fn execute(opts: &Opts) {
 
 	todos.append(&mut new_todos);
 
-	write_todos_to_default_file(&todos).expect("Could not write todos to default file");
+	write_todos_to_default_file(todo_list).expect("Could not write todos to default file");
 }
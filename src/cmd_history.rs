@@ -0,0 +1,86 @@
+use todo_txt::audit::{read_events, Event};
+use todo_txt::todo_file::parse_todos_from_default_file;
+use todo_txt::todo_list::SelectError;
+
+use gumdrop::Options;
+use uuid::Uuid;
+
+#[derive(Debug, Options)]
+pub struct Opts {
+	#[options(help = "Print help message")]
+	help: bool,
+
+	#[options(free, help = "Todo number or id (prefix)")]
+	free: Vec<String>,
+}
+
+/// Id of a todo that is no longer in todo.txt, found by id prefix in
+/// the audit log
+fn find_logged_id(events: &[Event], selector: &str) -> Result<Uuid, String> {
+	let prefix = selector.trim_start_matches("id:").to_lowercase();
+	let mut ids: Vec<Uuid> = events
+		.iter()
+		.filter_map(|e| e.id)
+		.filter(|id| id.to_string().starts_with(&prefix))
+		.collect();
+
+	ids.sort();
+	ids.dedup();
+
+	match ids.len() {
+		0 => Err(format!("no history for '{}'", selector)),
+		1 => Ok(ids[0]),
+		_ => Err(format!(
+			"'{}' is ambiguous, it matches ids {}",
+			selector,
+			ids.iter()
+				.map(|id| id.to_string()[..8].to_string())
+				.collect::<Vec<String>>()
+				.join(", ")
+		)),
+	}
+}
+
+fn print_event(e: &Event) {
+	println!(
+		"{}  {:<13} {}",
+		e.time.format("%Y-%m-%d %H:%M:%S"),
+		e.action.as_str(),
+		e.command
+	);
+
+	if let Some(before) = &e.before {
+		println!("    - {}", before);
+	}
+
+	if let Some(after) = &e.after {
+		println!("    + {}", after);
+	}
+}
+
+pub fn execute(opts: &Opts) {
+	let selector = match opts.free.first() {
+		Some(s) => s,
+		None => {
+			eprintln!("no todo given");
+			std::process::exit(1);
+		}
+	};
+	let events = read_events().expect("Could not read audit log");
+	let todo_list =
+		parse_todos_from_default_file().expect("Could not parse todos from default file");
+
+	let id = match todo_list.find(selector) {
+		Ok(position) => Ok(todo_list.items[position].id),
+		Err(SelectError::NotFound(_)) => find_logged_id(&events, selector),
+		Err(e) => Err(e.to_string()),
+	}
+	.unwrap_or_else(|e| {
+		eprintln!("{}", e);
+		std::process::exit(1);
+	});
+
+	for e in events.iter().filter(|e| e.id == Some(id)) {
+		print_event(e);
+	}
+}
@@ -0,0 +1,135 @@
+use todo_txt::cfg::{
+	get_archive_filename, get_default_config_filename, get_todo_filename, read_config,
+};
+
+use gumdrop::Options;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Options)]
+pub struct Opts {
+	#[options(help = "Print help message")]
+	help: bool,
+}
+
+const STARTER_CONFIG: &str = r#"# todo-txt configuration
+#
+# Every option is commented out and shows its default value. See the
+# README for details.
+
+# Directory todo.txt and archive.txt are kept in, relative to this file.
+# Defaults to todo-txt in the configuration directory of the system,
+# such as ~/.config/todo-txt on Linux. ~ is not expanded.
+DATA_PATH
+
+# Use other files than todo.txt and archive.txt in data_path. Relative
+# paths are relative to this file.
+# todo_filename = "todo.txt"
+# archive_filename = "archive.txt"
+
+# Show the todo list when no command is given
+# auto_ls = false
+
+# Store the date tasks are created and completed on
+# log_create_date = false
+# log_complete_date = false
+
+# Archive tasks as soon as they are marked done
+# auto_archive = false
+
+# Archive to one file per "monthly" or "yearly" period, such as
+# archive-2026-10.txt, and gzip the files of past periods
+# archive_rotation = "none"
+# archive_compress = false
+
+# Priority of new tasks, A-Z
+# default_priority = "C"
+
+# How tasks are identified: "uuid", "short" or "none", and the key the
+# id is written under
+# id_strategy = "uuid"
+# id_key = "id"
+
+# Seconds to wait for another todo-txt command to finish
+# lock_timeout = 10
+
+# Snapshots of todo.txt kept before each change, 0 turns them off
+# backup_count = 10
+# backup_path = "backups"
+
+# Commands remembered for undo and redo, 0 turns the journal off
+# journal_size = 100
+
+# Log of every change to a task, "" turns it off
+# audit_log = "audit.jsonl"
+
+# Commit todo.txt and archive.txt to a git repository in their directory
+# after every change, see the log and revert commands
+# git_storage = false
+
+# Tags that can not be used together, tagging a task with one removes
+# the others
+# mutually_exclusive_tags = [["today", "tomorrow", "someday"]]
+
+# Text appended to tasks added to a project
+# [project_rules.Work]
+# append = "@office"
+"#;
+
+/// Starter configuration. One given with `--config` keeps todo.txt next
+/// to it, the default one uses the default data path, which is its own
+/// directory.
+fn starter_config(is_default: bool) -> String {
+	let data_path = match is_default {
+		true => "# data_path = \".\"",
+		false => "data_path = \".\"",
+	};
+
+	STARTER_CONFIG.replace("DATA_PATH", data_path)
+}
+
+/// Create `path` and its directory unless it exists. Returns true if it
+/// was created.
+fn create_file(path: &Path, content: &str) -> Result<bool, io::Error> {
+	if let Some(dir) = path.parent() {
+		fs::create_dir_all(dir)?;
+	}
+
+	match OpenOptions::new().write(true).create_new(true).open(path) {
+		Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(false),
+		Err(e) => Err(e),
+		Ok(_) if content.is_empty() => Ok(true),
+		Ok(_) => fs::write(path, content).map(|_| true),
+	}
+}
+
+fn init_file(path: &Path, content: &str) {
+	match create_file(path, content) {
+		Err(e) => {
+			eprintln!("could not create {}: {}", path.display(), e);
+			std::process::exit(1);
+		}
+		Ok(true) => println!("Created {}", path.display()),
+		Ok(false) => println!("Kept existing {}", path.display()),
+	}
+}
+
+/// Create the configuration file (`config_file`, or the default one) and
+/// the empty todo.txt and archive.txt files it names. Existing files are
+/// kept as is.
+pub fn execute(_opts: &Opts, config_file: Option<&str>) {
+	let config_path = match config_file {
+		Some(f) => PathBuf::from(f),
+		None => get_default_config_filename(),
+	};
+
+	init_file(&config_path, &starter_config(config_file.is_none()));
+
+	if let Err(e) = read_config(config_path.to_str()) {
+		eprintln!("could not read {}: {}", config_path.display(), e);
+		std::process::exit(1);
+	}
+	init_file(Path::new(&get_todo_filename()), "");
+	init_file(Path::new(&get_archive_filename()), "");
+}
@@ -0,0 +1,27 @@
+use todo_txt::cfg::get_git_storage;
+use todo_txt::git;
+
+use gumdrop::Options;
+
+#[derive(Debug, Options)]
+pub struct Opts {
+	#[options(help = "Print help message")]
+	help: bool,
+
+	#[options(help = "Number of commits to show, 10 by default", meta = "N")]
+	count: Option<usize>,
+}
+
+pub fn execute(opts: &Opts) {
+	if !get_git_storage() {
+		eprintln!("log needs git_storage to be turned on");
+		std::process::exit(1);
+	}
+
+	let log = git::log(opts.count.unwrap_or(10)).unwrap_or_else(|e| {
+		eprintln!("{}", e);
+		std::process::exit(1);
+	});
+
+	print!("{}", log);
+}
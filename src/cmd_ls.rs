@@ -1,9 +1,15 @@
-use crate::todo::Todo;
-use crate::todo_file;
+use todo_txt::cfg::{
+	get_archive_filename, get_list_archive_filename, get_list_names, get_list_todo_filename,
+	get_todo_filename,
+};
+use todo_txt::query::Query;
+use todo_txt::todo::Todo;
+use todo_txt::todo_file;
+use todo_txt::todo_list::TodoList;
 
-use atty;
 use gumdrop::Options;
-use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+use std::io::{self, Write};
+use termcolor::{BufferedStandardStream, Color, ColorChoice, ColorSpec, WriteColor};
 
 #[derive(Debug, Options)]
 pub struct Opts {
@@ -22,6 +28,12 @@ pub struct Opts {
 	#[options(help = "Only past due todos")]
 	past_due: bool,
 
+	#[options(help = "Include todos with a threshold date (t:) in the future")]
+	future: bool,
+
+	#[options(help = "Only todos tagged with #TAG (may be repeated)", meta = "TAG")]
+	tag: Vec<String>,
+
 	#[options(help = "Order by title only")]
 	title_order: bool,
 
@@ -33,6 +45,15 @@ pub struct Opts {
 
 	#[options(help = "Enable color output (auto, always, never)")]
 	color: String,
+
+	#[options(help = "List todos of every list, labeled with their list")]
+	all_lists: bool,
+
+	#[options(help = "List archived todos instead, numbered a1, a2, ...")]
+	archived: bool,
+
+	#[options(help = "List archived todos after the others")]
+	all: bool,
 }
 
 pub fn default_opts() -> Opts {
@@ -42,14 +63,26 @@ pub fn default_opts() -> Opts {
 		priority: '\0',
 		incomplete: false,
 		past_due: false,
+		future: false,
+		tag: vec![],
 		title_order: false,
 		due_date_order: false,
 		limit: 0,
 		color: String::from("auto"),
+		all_lists: false,
+		archived: false,
+		all: false,
 	}
 }
 
-fn print_todo(stream: &mut termcolor::StandardStream, todo: &Todo) {
+/// Print `todo`, after `label`. Archived todos are numbered a1, a2, ...
+/// so they can not be mistaken for todos of todo.txt.
+fn print_todo(
+	stream: &mut BufferedStandardStream,
+	todo: &Todo,
+	label: &str,
+	archived: bool,
+) -> io::Result<()> {
 	let priority_color = match todo.priority {
 		Some('A') => Color::Red,
 		Some('B') => Color::Cyan,
@@ -58,45 +91,32 @@ fn print_todo(stream: &mut termcolor::StandardStream, todo: &Todo) {
 		None => Color::White,
 	};
 
-	stream
-		.set_color(ColorSpec::new().set_fg(Some(Color::White)))
-		.expect("Could not set foreground color");
+	stream.set_color(ColorSpec::new().set_fg(Some(Color::White)))?;
 
-	print!("  {:3}: ", todo.index + 1);
+	let number = format!("{}{}", if archived { "a" } else { "" }, todo.index + 1);
 
-	print!("[");
+	write!(stream, "  {}{:>3}: {} ", label, number, todo.short_id())?;
 
-	stream
-		.set_color(ColorSpec::new().set_fg(Some(Color::Green)))
-		.expect("Could not set foreground color");
+	write!(stream, "[")?;
 
-	print!("{}", if todo.is_complete { "X" } else { " " });
+	stream.set_color(ColorSpec::new().set_fg(Some(Color::Green)))?;
 
-	stream
-		.set_color(ColorSpec::new().set_fg(Some(Color::White)))
-		.expect("Could not set foreground color");
+	write!(stream, "{}", if todo.is_complete { "X" } else { " " })?;
 
-	print!("] (");
+	stream.set_color(ColorSpec::new().set_fg(Some(Color::White)))?;
 
-	stream
-		.set_color(ColorSpec::new().set_fg(Some(priority_color)))
-		.expect("Could not set foreground color");
+	write!(stream, "] (")?;
 
-	print!(
-		"{}",
-		match todo.priority {
-			None => ' ',
-			Some(v) => v,
-		}
-	);
+	stream.set_color(ColorSpec::new().set_fg(Some(priority_color)))?;
+
+	write!(stream, "{}", todo.priority.unwrap_or(' '))?;
 
-	stream
-		.set_color(ColorSpec::new().set_fg(Some(Color::White)))
-		.expect("Could not set foreground color");
+	stream.set_color(ColorSpec::new().set_fg(Some(Color::White)))?;
 
-	print!(") ");
+	write!(stream, ") ")?;
 
-	let words = todo.task.split_whitespace();
+	let task = todo.task();
+	let words = task.split_whitespace();
 
 	for word in words {
 		let color = match word.chars().next() {
@@ -106,27 +126,20 @@ fn print_todo(stream: &mut termcolor::StandardStream, todo: &Todo) {
 			_ => Color::White,
 		};
 
-		stream
-			.set_color(ColorSpec::new().set_fg(Some(color)))
-			.expect("Could not set foreground color");
-		print!("{} ", word);
+		stream.set_color(ColorSpec::new().set_fg(Some(color)))?;
+		write!(stream, "{} ", word)?;
 	}
 
-	stream
-		.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))
-		.expect("Could not set foreground color");
+	stream.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))?;
 
-	println!(" {}", todo.elapsed_time());
+	writeln!(stream, " {}", todo.elapsed_time())?;
 
-	stream
-		.set_color(ColorSpec::new().set_fg(Some(Color::White)))
-		.expect("Could not set foreground color");
+	stream.set_color(ColorSpec::new().set_fg(Some(Color::White)))
 }
 
-pub fn execute(opts: &Opts) {
-	let mut todo_list =
-		todo_file::parse_todos_from_default_file().expect("Could not parse default todo.txt file");
-
+/// Todos of `todo_list` matching `query` to show, in the order to show
+/// them
+fn filter_and_sort(mut todo_list: TodoList, query: &Query, opts: &Opts) -> TodoList {
 	if opts.priority >= 'A' {
 		let priority_ch = opts.priority.to_uppercase().next().unwrap();
 
@@ -141,10 +154,16 @@ pub fn execute(opts: &Opts) {
 		todo_list = todo_list.filter_by_past_due(true);
 	}
 
-	for text in &opts.free {
-		todo_list = todo_list.filter_by_text(text);
+	if !opts.future {
+		todo_list = todo_list.filter_by_threshold(true);
+	}
+
+	for tag in &opts.tag {
+		todo_list = todo_list.filter_by_tag(tag);
 	}
 
+	todo_list = todo_list.filter_by_query(query);
+
 	if opts.title_order {
 		todo_list.sort_by_title();
 	} else if opts.due_date_order {
@@ -157,10 +176,28 @@ pub fn execute(opts: &Opts) {
 		todo_list.items = todo_list.items.into_iter().take(5).collect();
 	}
 
+	todo_list
+}
+
+pub fn execute(opts: &Opts) {
+	let query = Query::parse(&opts.free).unwrap_or_else(|e| {
+		eprintln!("{}", e);
+		std::process::exit(1);
+	});
+
+	// Lines without these can not be listed, and are not parsed
+	let mut words = query.required_words();
+
+	words.extend(
+		opts.tag
+			.iter()
+			.map(|t| format!("#{}", t.trim_start_matches('#'))),
+	);
+
 	let color_choice = match opts.color.to_ascii_lowercase().as_str() {
 		"always" => ColorChoice::Always,
 		"never" => ColorChoice::Never,
-		"auto" | "" | _ => {
+		_ => {
 			if atty::is(atty::Stream::Stdout) {
 				ColorChoice::Auto
 			} else {
@@ -169,9 +206,51 @@ pub fn execute(opts: &Opts) {
 		}
 	};
 
-	let mut stdout = StandardStream::stdout(color_choice);
+	// Buffered, as large lists are slow to print line by line
+	let mut stdout = BufferedStandardStream::stdout(color_choice);
+
+	// label, todo.txt and archive.txt of each list to show
+	let lists = if opts.all_lists {
+		let names = get_list_names();
+		let width = names.iter().map(|n| n.len()).max().unwrap_or(0);
+
+		names
+			.iter()
+			.map(|name| {
+				(
+					format!("{:width$} ", name, width = width),
+					get_list_todo_filename(name),
+					get_list_archive_filename(name),
+				)
+			})
+			.collect()
+	} else {
+		vec![(String::new(), get_todo_filename(), get_archive_filename())]
+	};
+
+	for (label, todo_filename, archive_filename) in lists {
+		let mut files = Vec::new();
+
+		if !opts.archived {
+			files.push((todo_filename, false));
+		}
 
-	for t in todo_list.items {
-		print_todo(&mut stdout, &t);
+		if opts.archived || opts.all {
+			files.push((archive_filename, true));
+		}
+
+		for (filename, archived) in files {
+			let todo_list = match archived {
+				true => todo_file::parse_archive_containing_and_warn(&filename, &words),
+				false => todo_file::parse_todos_containing_and_warn(&filename, &words),
+			}
+			.expect("Could not parse todo file");
+
+			for t in filter_and_sort(todo_list, &query, opts).items {
+				print_todo(&mut stdout, &t, &label, archived).expect("Could not print todo");
+			}
+		}
 	}
+
+	stdout.flush().expect("Could not print todo");
 }
@@ -0,0 +1,88 @@
+use todo_txt::encoding::{self, encode, Decoded};
+use todo_txt::merge::{count_conflicts, merge, to_text, Merged};
+use todo_txt::todo_file::write_file_atomically;
+
+use gumdrop::Options;
+use std::io::prelude::Write;
+
+#[derive(Debug, Options)]
+pub struct Opts {
+	#[options(help = "Print help message")]
+	help: bool,
+
+	#[options(help = "Print the result instead of writing it to OURS")]
+	print: bool,
+
+	#[options(free, help = "BASE OURS THEIRS, as git passes %O %A %B")]
+	free: Vec<String>,
+}
+
+fn read(filename: &str) -> Decoded {
+	let decoded = encoding::read_file(filename).unwrap_or_else(|e| {
+		eprintln!("{}: {}", filename, e);
+		std::process::exit(2);
+	});
+
+	for e in &decoded.errors {
+		eprintln!("warning: {}:{}", filename, e);
+	}
+
+	decoded
+}
+
+/// Merge the todo files OURS and THEIRS, both changed from BASE, into
+/// OURS. Exits with 1 if there are conflicts, the way git expects of a
+/// merge driver.
+pub fn execute(opts: &Opts) {
+	let (base, ours, theirs) = match opts.free.as_slice() {
+		[base, ours, theirs] => (base, ours, theirs),
+		_ => {
+			eprintln!("merge needs the files BASE OURS THEIRS");
+			std::process::exit(2);
+		}
+	};
+
+	let (base_file, our_file, their_file) = (read(base), read(ours), read(theirs));
+	let merged = merge(&base_file.text, &our_file.text, &their_file.text);
+	let text = to_text(&merged);
+
+	if opts.print {
+		print!("{}", text);
+	} else {
+		// Invalid bytes of any side would be written as U+FFFD
+		for (filename, file) in [(base, &base_file), (ours, &our_file), (theirs, &their_file)] {
+			encoding::check_rewrite(filename, &file.errors).unwrap_or_else(|e| {
+				eprintln!("{}", e);
+				std::process::exit(2);
+			});
+		}
+
+		let bytes = encode(&text, our_file.format);
+
+		write_file_atomically(ours, |w| w.write_all(&bytes)).unwrap_or_else(|e| {
+			eprintln!("{}: {}", ours, e);
+			std::process::exit(2);
+		});
+	}
+
+	for m in &merged {
+		if let Merged::Conflict {
+			ours,
+			theirs,
+			fields,
+		} = m
+		{
+			let line = ours
+				.as_ref()
+				.or(theirs.as_ref())
+				.cloned()
+				.unwrap_or_default();
+
+			eprintln!("conflict ({}): {}", fields.join(", "), line);
+		}
+	}
+
+	if count_conflicts(&merged) > 0 {
+		std::process::exit(1);
+	}
+}
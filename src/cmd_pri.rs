@@ -1,5 +1,7 @@
-use crate::todo_file::{parse_todos_from_default_file, write_todos_to_default_file};
 use gumdrop::Options;
+use todo_txt::todo_file::{
+	lock_default_file_for_rewrite, parse_todos_from_default_file, write_todos_to_default_file,
+};
 
 #[derive(Debug, Options)]
 pub struct Opts {
@@ -14,6 +16,9 @@ pub struct Opts {
 
 	#[options(free)]
 	free: Vec<String>,
+
+	#[options(help = "Also todos matching QUERY, as ls takes it", meta = "QUERY")]
+	matching: String,
 }
 
 pub fn execute(opts: &Opts) {
@@ -22,16 +27,22 @@ pub fn execute(opts: &Opts) {
 		Some(_) if opts.clear => None,
 		Some(p) => p.to_uppercase().next(),
 	};
-	let todos = &mut parse_todos_from_default_file()
-		.expect("Could not parse todos from default file")
-		.items;
-
-	for num in opts.free.iter().map(|i| i.parse::<usize>().unwrap() - 1) {
-		match todos.get_mut(num) {
-			None => println!("todo {} was not found", num + 1),
-			Some(t) => t.priority = priority,
-		}
+	let _lock = lock_default_file_for_rewrite().unwrap_or_else(|e| {
+		eprintln!("{}", e);
+		std::process::exit(1);
+	});
+	let todo_list =
+		&mut parse_todos_from_default_file().expect("Could not parse todos from default file");
+	let positions = todo_list
+		.select(&opts.free, &opts.matching)
+		.unwrap_or_else(|e| {
+			eprintln!("{}", e);
+			std::process::exit(1);
+		});
+
+	for position in positions {
+		todo_list.items[position].priority = priority;
 	}
 
-	write_todos_to_default_file(todos).expect("Could not write todos to default file");
+	write_todos_to_default_file(todo_list).expect("Could not write todos to default file");
 }
@@ -0,0 +1,33 @@
+use crate::cmd_undo::describe;
+use todo_txt::journal::redo;
+use todo_txt::todo_file::lock_default_file_for_rewrite;
+
+use gumdrop::Options;
+
+#[derive(Debug, Options)]
+pub struct Opts {
+	#[options(help = "Print help message")]
+	help: bool,
+
+	#[options(free, help = "Number of commands to redo, 1 by default")]
+	count: Option<usize>,
+}
+
+pub fn execute(opts: &Opts) {
+	let _lock = lock_default_file_for_rewrite().unwrap_or_else(|e| {
+		eprintln!("{}", e);
+		std::process::exit(1);
+	});
+	let operations = redo(opts.count.unwrap_or(1)).unwrap_or_else(|e| {
+		eprintln!("could not redo, {}", e);
+		std::process::exit(1);
+	});
+
+	if operations.is_empty() {
+		println!("Nothing to redo");
+	}
+
+	for op in operations {
+		println!("Redid {}", describe(&op));
+	}
+}
@@ -1,17 +1,18 @@
-use crate::cfg::get_data_filename;
-use crate::cfg::get_note_file_extension;
-use crate::hms;
-use crate::todo::Todo;
-use crate::todo_file;
-use crate::todo_list::TodoList;
+use todo_txt::cfg::get_data_filename;
+use todo_txt::cfg::get_note_file_extension;
+use todo_txt::cfg::{get_archive_filename, get_todo_filename};
+use todo_txt::hms;
+use todo_txt::query::Query;
+use todo_txt::todo::Todo;
+use todo_txt::todo_file;
+use todo_txt::todo_list::TodoList;
 
 use std::fs::File;
 use std::io;
-use std::io::Read;
+use std::io::{Read, Write};
 
-use atty;
 use gumdrop::Options;
-use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+use termcolor::{BufferedStandardStream, Color, ColorChoice, ColorSpec, WriteColor};
 
 #[derive(Debug, Options)]
 pub struct Opts {
@@ -26,6 +27,12 @@ pub struct Opts {
 
 	#[options(help = "Enable color output (auto, always, never)")]
 	color: String,
+
+	#[options(help = "Report on archived todos instead")]
+	archived: bool,
+
+	#[options(help = "Report on archived todos as well")]
+	all: bool,
 }
 
 fn read_file(filename: &str) -> io::Result<String> {
@@ -38,7 +45,7 @@ fn read_file(filename: &str) -> io::Result<String> {
 	Ok(project_docs)
 }
 
-fn print_todo(stream: &mut termcolor::StandardStream, todo: &Todo) {
+fn print_todo(stream: &mut BufferedStandardStream, todo: &Todo) -> io::Result<()> {
 	let priority_color = match todo.priority {
 		Some('A') => Color::Red,
 		Some('B') => Color::Cyan,
@@ -47,31 +54,20 @@ fn print_todo(stream: &mut termcolor::StandardStream, todo: &Todo) {
 		None => Color::White,
 	};
 
-	stream
-		.set_color(ColorSpec::new().set_fg(Some(Color::White)))
-		.expect("Could not set foreground color");
+	stream.set_color(ColorSpec::new().set_fg(Some(Color::White)))?;
 
-	print!("  (");
+	write!(stream, "  (")?;
 
-	stream
-		.set_color(ColorSpec::new().set_fg(Some(priority_color)))
-		.expect("Could not set foreground color");
+	stream.set_color(ColorSpec::new().set_fg(Some(priority_color)))?;
 
-	print!(
-		"{}",
-		match todo.priority {
-			None => ' ',
-			Some(v) => v,
-		}
-	);
+	write!(stream, "{}", todo.priority.unwrap_or(' '))?;
 
-	stream
-		.set_color(ColorSpec::new().set_fg(Some(Color::White)))
-		.expect("Could not set foreground color");
+	stream.set_color(ColorSpec::new().set_fg(Some(Color::White)))?;
 
-	print!(") ");
+	write!(stream, ") ")?;
 
-	let words = todo.task.split_whitespace();
+	let task = todo.task();
+	let words = task.split_whitespace();
 
 	for word in words {
 		let color = match word.chars().next() {
@@ -81,61 +77,65 @@ fn print_todo(stream: &mut termcolor::StandardStream, todo: &Todo) {
 			_ => Color::White,
 		};
 
-		stream
-			.set_color(ColorSpec::new().set_fg(Some(color)))
-			.expect("Could not set foreground color");
-		print!("{} ", word);
+		stream.set_color(ColorSpec::new().set_fg(Some(color)))?;
+		write!(stream, "{} ", word)?;
 	}
 
-	stream
-		.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))
-		.expect("Could not set foreground color");
+	stream.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))?;
 
-	println!(" {}", todo.elapsed_time());
+	writeln!(stream, " {}", todo.elapsed_time())
 }
 
-fn print_todo_list(stream: &mut termcolor::StandardStream, list: TodoList) {
+fn print_todo_list(stream: &mut BufferedStandardStream, list: TodoList) -> io::Result<()> {
 	for t in list.items {
-		let mut out = Vec::new();
-
-		match t.priority {
-			Some(p) => out.push(format!("({})", p)),
-			None => out.push(String::from("   ")),
-		}
-
-		out.push(t.task.clone());
-
-		let kv_pairs: Vec<std::string::String> = t
-			.key_values
-			.iter()
-			.map(|(k, v)| format!("{}:{}", k, v))
-			.collect();
-		let kv_pairs_str = kv_pairs.join(" ");
-
-		if kv_pairs_str.len() > 0 {
-			out.push(kv_pairs_str);
-		}
-
-		//println!("  {:9} {}", t.elapsed_time(), out.join(" "));
-		print_todo(stream, &t);
+		print_todo(stream, &t)?;
 	}
+
+	Ok(())
 }
 
 pub fn execute(opts: &Opts) {
-	let project_name = &opts.free[0];
-	if opts.free.len() != 1 {
+	if opts.free.is_empty() {
 		panic!("no project name given");
 	}
 
+	let project_name = &opts.free[0];
+	let query = Query::parse(&opts.free[1..]).unwrap_or_else(|e| {
+		eprintln!("{}", e);
+		std::process::exit(1);
+	});
+
 	let project_documentation: String =
 		match read_file(format!("files/{}.{}", project_name, get_note_file_extension()).as_str()) {
 			Err(_) => String::new(),
 			Ok(content) => content.split("\n").collect::<Vec<&str>>().join("\n  "),
 		};
 
-	let mut todo_list = todo_file::parse_todos_from_default_file()
-		.expect("Could not parse default todo.txt file")
-		.filter_by_project(&project_name);
+	// Lines without these can not be reported on, and are not parsed
+	let mut words = query.required_words();
+
+	words.push(format!("+{}", project_name));
+
+	let mut todo_list = TodoList::new(vec![]);
+
+	if !opts.archived {
+		let live = todo_file::parse_todos_containing_and_warn(&get_todo_filename(), &words)
+			.expect("Could not parse default todo.txt file");
+
+		todo_list.items.extend(live.items);
+	}
+
+	if opts.archived || opts.all {
+		let archived =
+			todo_file::parse_archive_containing_and_warn(&get_archive_filename(), &words)
+				.expect("Could not parse default archive.txt file");
+
+		todo_list.items.extend(archived.items);
+	}
+
+	let mut todo_list = todo_list
+		.filter_by_project(project_name)
+		.filter_by_query(&query);
 
 	todo_list.sort();
 
@@ -149,7 +149,7 @@ pub fn execute(opts: &Opts) {
 	let color_choice = match opts.color.to_ascii_lowercase().as_str() {
 		"always" => ColorChoice::Always,
 		"never" => ColorChoice::Never,
-		"auto" | "" | _ => {
+		_ => {
 			if atty::is(atty::Stream::Stdout) {
 				ColorChoice::Auto
 			} else {
@@ -158,48 +158,66 @@ pub fn execute(opts: &Opts) {
 		}
 	};
 
-	let mut stream = StandardStream::stdout(color_choice);
+	// Buffered, as large reports are slow to print line by line
+	let mut stream = BufferedStandardStream::stdout(color_choice);
+
+	print_report(
+		&mut stream,
+		project_name,
+		&project_documentation,
+		time_spent,
+		open_todos,
+		closed_todos,
+	)
+	.expect("Could not print report");
+}
+
+fn print_report(
+	stream: &mut BufferedStandardStream,
+	project_name: &str,
+	project_documentation: &str,
+	time_spent: i64,
+	open_todos: TodoList,
+	closed_todos: TodoList,
+) -> io::Result<()> {
 	let open_task_count = open_todos.items.len();
 	let closed_task_count = closed_todos.items.len();
 	let total_task_count = open_task_count + closed_task_count;
 
-	stream
-		.set_color(ColorSpec::new().set_fg(Some(Color::White)))
-		.expect("Could not set foreground color");
+	stream.set_color(ColorSpec::new().set_fg(Some(Color::White)))?;
 
-	println!("# {}\n", project_name);
-	println!("  {}", project_documentation);
+	writeln!(stream, "# {}\n", project_name)?;
+	writeln!(stream, "  {}", project_documentation)?;
 
-	println!("# Task Statistics\n");
-	println!("  -       Open: {}", open_task_count);
-	println!("  -     Closed: {}", closed_task_count);
-	println!("  -      Total: {}", total_task_count);
-	println!(
+	writeln!(stream, "# Task Statistics\n")?;
+	writeln!(stream, "  -       Open: {}", open_task_count)?;
+	writeln!(stream, "  -     Closed: {}", closed_task_count)?;
+	writeln!(stream, "  -      Total: {}", total_task_count)?;
+	writeln!(
+		stream,
 		"  - Completion: {:.0}%",
 		(closed_task_count as f32 / total_task_count as f32) * 100.0
-	);
-	println!("  -       Time: {}", hms::from_seconds(time_spent));
-	println!("");
-
-	if open_todos.items.len() > 0 {
-		stream
-			.set_color(ColorSpec::new().set_fg(Some(Color::White)))
-			.expect("Could not set foreground color");
-
-		println!("# Open Tasks");
-		println!("");
-		print_todo_list(&mut stream, open_todos);
-		println!("");
+	)?;
+	writeln!(stream, "  -       Time: {}", hms::from_seconds(time_spent))?;
+	writeln!(stream)?;
+
+	if !open_todos.items.is_empty() {
+		stream.set_color(ColorSpec::new().set_fg(Some(Color::White)))?;
+
+		writeln!(stream, "# Open Tasks")?;
+		writeln!(stream)?;
+		print_todo_list(stream, open_todos)?;
+		writeln!(stream)?;
 	}
 
-	if closed_todos.items.len() > 0 {
-		stream
-			.set_color(ColorSpec::new().set_fg(Some(Color::White)))
-			.expect("Could not set foreground color");
+	if !closed_todos.items.is_empty() {
+		stream.set_color(ColorSpec::new().set_fg(Some(Color::White)))?;
 
-		println!("# Closed Tasks");
-		println!("");
-		print_todo_list(&mut stream, closed_todos);
-		println!("");
+		writeln!(stream, "# Closed Tasks")?;
+		writeln!(stream)?;
+		print_todo_list(stream, closed_todos)?;
+		writeln!(stream)?;
 	}
+
+	stream.flush()
 }
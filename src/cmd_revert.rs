@@ -0,0 +1,53 @@
+use todo_txt::cfg::get_git_storage;
+use todo_txt::git;
+use todo_txt::journal::{self, JournalFile};
+use todo_txt::todo_file::{lock_default_file, read_default_text};
+
+use gumdrop::Options;
+
+#[derive(Debug, Options)]
+pub struct Opts {
+	#[options(help = "Print help message")]
+	help: bool,
+
+	#[options(free, help = "Commit to revert, as shown by log")]
+	commit: String,
+}
+
+pub fn execute(opts: &Opts) {
+	if !get_git_storage() {
+		eprintln!("revert needs git_storage to be turned on");
+		std::process::exit(1);
+	}
+
+	if opts.commit.is_empty() {
+		eprintln!("no commit given, see log");
+		std::process::exit(1);
+	}
+
+	let _lock = lock_default_file().unwrap_or_else(|e| {
+		eprintln!("{}", e);
+		std::process::exit(1);
+	});
+	let files = [JournalFile::Todo, JournalFile::Archive];
+	let before: Vec<String> = files
+		.iter()
+		.map(|f| read_default_text(*f).expect("Could not read todo files"))
+		.collect();
+
+	git::revert(&opts.commit).unwrap_or_else(|e| {
+		eprintln!("could not revert {}, {}", opts.commit, e);
+		std::process::exit(1);
+	});
+
+	// Journal the lines git changed, so revert can be undone too
+	let mut changes = Vec::new();
+
+	for (file, before) in files.iter().zip(before) {
+		let after = read_default_text(*file).expect("Could not read todo files");
+
+		changes.extend(journal::diff(*file, &before, &after));
+	}
+
+	journal::record(changes).expect("Could not record changes in the journal");
+}
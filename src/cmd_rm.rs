@@ -1,5 +1,7 @@
-use crate::todo_file::{parse_todos_from_default_file, write_todos_to_default_file};
 use gumdrop::Options;
+use todo_txt::todo_file::{
+	lock_default_file_for_rewrite, parse_todos_from_default_file, write_todos_to_default_file,
+};
 
 #[derive(Debug, Options)]
 pub struct Opts {
@@ -8,27 +10,32 @@ pub struct Opts {
 
 	#[options(free)]
 	free: Vec<String>,
+
+	#[options(help = "Also todos matching QUERY, as ls takes it", meta = "QUERY")]
+	matching: String,
 }
 
 pub fn execute(opts: &Opts) {
+	let _lock = lock_default_file_for_rewrite().unwrap_or_else(|e| {
+		eprintln!("{}", e);
+		std::process::exit(1);
+	});
 	let todo_list =
 		&mut parse_todos_from_default_file().expect("Could not parse todos from default file");
+	let mut indexes_to_remove = todo_list
+		.select(&opts.free, &opts.matching)
+		.unwrap_or_else(|e| {
+			eprintln!("{}", e);
+			std::process::exit(1);
+		});
 	let todos = &mut todo_list.items;
 
-	let mut indexes_to_remove = Vec::new();
-
-	for id in &opts.free {
-		let iid = id.parse::<usize>().unwrap();
-		if let Some(t) = todos.get_mut(iid - 1) {
-			indexes_to_remove.push(t.index);
-		}
-	}
-
 	indexes_to_remove.sort();
+	indexes_to_remove.dedup();
 
 	for index in indexes_to_remove.iter().rev() {
-		todos.remove(*index as usize);
+		todos.remove(*index);
 	}
 
-	write_todos_to_default_file(&todos).expect("Could not write todos to default file");
+	write_todos_to_default_file(todo_list).expect("Could not write todos to default file");
 }
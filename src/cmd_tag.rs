@@ -1,6 +1,8 @@
-use crate::cfg::get_mutually_exclusive_tags;
-use crate::todo_file::{parse_todos_from_default_file, write_todos_to_default_file};
 use gumdrop::Options;
+use todo_txt::cfg::get_mutually_exclusive_tags;
+use todo_txt::todo_file::{
+	lock_default_file_for_rewrite, parse_todos_from_default_file, write_todos_to_default_file,
+};
 
 #[derive(Debug, Options)]
 pub struct Opts {
@@ -9,47 +11,55 @@ pub struct Opts {
 
 	#[options(free)]
 	free: Vec<String>,
+
+	#[options(help = "Also todos matching QUERY, as ls takes it", meta = "QUERY")]
+	matching: String,
 }
 
 pub fn execute(opts: &Opts) {
 	let mut free_it = opts.free.iter();
 	let tag_name = match free_it.next() {
-		Some(v) => v,
+		Some(v) => v.trim_start_matches('#'),
 		None => panic!("No tag name given!"),
 	};
-	let tag_name_formatted = format!(" #{}", tag_name);
 	let mutually_exclusive_tags: Vec<Vec<String>> = get_mutually_exclusive_tags()
 		.into_iter()
-		.filter(|t| t.contains(&tag_name))
+		.filter(|t| t.iter().any(|tag| tag == tag_name))
 		.collect();
+	let _lock = lock_default_file_for_rewrite().unwrap_or_else(|e| {
+		eprintln!("{}", e);
+		std::process::exit(1);
+	});
 	let todo_list =
 		&mut parse_todos_from_default_file().expect("Could not parse todos from default file");
+	let selectors: Vec<String> = free_it.cloned().collect();
+	let positions = todo_list
+		.select(&selectors, &opts.matching)
+		.unwrap_or_else(|e| {
+			eprintln!("{}", e);
+			std::process::exit(1);
+		});
 	let todos = &mut todo_list.items;
 
-	for id in free_it {
-		let iid = id.parse::<usize>().unwrap();
-		if let Some(t) = todos.get_mut(iid - 1) {
+	for position in positions {
+		if let Some(t) = todos.get_mut(position) {
 			for tags in &mutually_exclusive_tags {
 				for tag in tags {
 					if tag == tag_name {
 						continue;
 					}
 
-					let tnf = format!(" #{}", tag);
-
-					if t.task.contains(&tnf) {
-						t.task = t.task.replace(&tnf, "");
-					}
+					t.remove_tag(tag);
 				}
 			}
 
-			if t.task.contains(&tag_name_formatted) {
-				t.task = t.task.replace(&tag_name_formatted, "");
+			if t.has_tag(tag_name) {
+				t.remove_tag(tag_name);
 			} else {
-				t.task = format!("{}{}", t.task, tag_name_formatted);
+				t.add_tag(tag_name);
 			}
 		}
 	}
 
-	write_todos_to_default_file(&todos).expect("Could not write todos to default file");
+	write_todos_to_default_file(todo_list).expect("Could not write todos to default file");
 }
@@ -0,0 +1,139 @@
+use gumdrop::Options;
+use todo_txt::todo_file::{
+	lock_default_file_for_rewrite, parse_todos_from_archive_file, parse_todos_from_default_file,
+	write_unarchived_todos,
+};
+use todo_txt::todo_list::{SelectError, TodoList, MIN_NUMERIC_ID_PREFIX};
+
+#[derive(Debug, Options)]
+pub struct Opts {
+	#[options(help = "Print help message")]
+	help: bool,
+
+	#[options(help = "Mark the todos as not done")]
+	reopen: bool,
+
+	#[options(help = "Unarchive every todo matching the search text")]
+	all: bool,
+
+	#[options(free)]
+	free: Vec<String>,
+}
+
+/// Positions in `archive` of the todos selected by `selector`: an
+/// archived number as shown by `ls --archived` (`a3`), a unique prefix of
+/// an id, or else text to search for. As with `TodoList::find`, a number
+/// is only taken for an id prefix as well from `MIN_NUMERIC_ID_PREFIX`
+/// characters on, and is ambiguous if it selects two todos.
+fn select(archive: &TodoList, selector: &str, all: bool) -> Result<Vec<usize>, String> {
+	let number = selector
+		.strip_prefix('a')
+		.and_then(|n| n.parse::<u32>().ok());
+	let by_number = number.and_then(|n| archive.items.iter().position(|t| t.index + 1 == n));
+	let id_selector = match selector.starts_with("id:") {
+		true => selector.to_string(),
+		false => format!("id:{}", selector),
+	};
+	let by_id = match number.is_none() || selector.len() >= MIN_NUMERIC_ID_PREFIX {
+		true => match archive.find(&id_selector) {
+			Ok(position) => Some(position),
+			Err(SelectError::NotFound(_)) => None,
+			Err(SelectError::Ambiguous(_, numbers)) => {
+				let numbers: Vec<String> = numbers.iter().map(|n| format!("a{}", n)).collect();
+
+				return Err(format!(
+					"'{}' is ambiguous, it matches archived todos {}",
+					selector,
+					numbers.join(", ")
+				));
+			}
+			Err(e) => return Err(e.to_string()),
+		},
+		false => None,
+	};
+
+	match (by_number, by_id) {
+		(Some(n), Some(i)) if n != i => {
+			return Err(format!(
+				"'{}' is ambiguous, it matches archived todos a{} by number and a{} by id",
+				selector,
+				archive.items[n].index + 1,
+				archive.items[i].index + 1
+			))
+		}
+		(Some(position), _) | (None, Some(position)) => return Ok(vec![position]),
+		(None, None) if number.is_some() => return Err(format!("no archived todo {}", selector)),
+		(None, None) => {}
+	}
+
+	let matches: Vec<usize> = archive
+		.items
+		.iter()
+		.enumerate()
+		.filter(|(_, t)| t.task().contains(selector))
+		.map(|(i, _)| i)
+		.collect();
+
+	match matches.len() {
+		0 => Err(format!("no archived todo matches '{}'", selector)),
+		1 => Ok(matches),
+		_ if all => Ok(matches),
+		_ => {
+			let numbers: Vec<String> = matches
+				.iter()
+				.map(|i| format!("a{}", archive.items[*i].index + 1))
+				.collect();
+
+			Err(format!(
+				"'{}' matches archived todos {}, use --all to unarchive them all",
+				selector,
+				numbers.join(", ")
+			))
+		}
+	}
+}
+
+pub fn execute(opts: &Opts) {
+	if opts.free.is_empty() {
+		eprintln!("no archived todo given, see ls --archived");
+		std::process::exit(1);
+	}
+
+	let _lock = lock_default_file_for_rewrite().unwrap_or_else(|e| {
+		eprintln!("{}", e);
+		std::process::exit(1);
+	});
+	let mut todo_list =
+		parse_todos_from_default_file().expect("Could not parse todos from default file");
+	let archive = parse_todos_from_archive_file().expect("Could not parse todos from archive file");
+
+	let mut positions = Vec::new();
+
+	for selector in &opts.free {
+		positions.extend(select(&archive, selector, opts.all).unwrap_or_else(|e| {
+			eprintln!("{}", e);
+			std::process::exit(1);
+		}));
+	}
+
+	positions.sort();
+	positions.dedup();
+
+	let mut numbers = Vec::new();
+
+	for position in &positions {
+		let mut t = archive.items[*position].clone();
+
+		if opts.reopen {
+			t.is_complete = false;
+			t.completed_at = None;
+		}
+
+		println!("unarchived: {}", t.task());
+
+		numbers.push(t.index);
+		todo_list.items.push(t);
+	}
+
+	write_unarchived_todos(&todo_list, &numbers).expect("Could not write todos to default files");
+}
@@ -0,0 +1,45 @@
+use todo_txt::journal::{undo, Change};
+use todo_txt::todo_file::lock_default_file_for_rewrite;
+
+use gumdrop::Options;
+
+#[derive(Debug, Options)]
+pub struct Opts {
+	#[options(help = "Print help message")]
+	help: bool,
+
+	#[options(free, help = "Number of commands to undo, 1 by default")]
+	count: Option<usize>,
+}
+
+/// Describe an operation (the changes of one command) on a line
+pub fn describe(operation: &[Change]) -> String {
+	let first = &operation[0];
+
+	format!(
+		"{} ({}, {} line{})",
+		first.command,
+		first.time.format("%Y-%m-%d %H:%M:%S"),
+		operation.len(),
+		if operation.len() == 1 { "" } else { "s" }
+	)
+}
+
+pub fn execute(opts: &Opts) {
+	let _lock = lock_default_file_for_rewrite().unwrap_or_else(|e| {
+		eprintln!("{}", e);
+		std::process::exit(1);
+	});
+	let operations = undo(opts.count.unwrap_or(1)).unwrap_or_else(|e| {
+		eprintln!("could not undo, {}", e);
+		std::process::exit(1);
+	});
+
+	if operations.is_empty() {
+		println!("Nothing to undo");
+	}
+
+	for op in operations {
+		println!("Undid {}", describe(&op));
+	}
+}
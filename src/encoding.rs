@@ -0,0 +1,205 @@
+/// Decoding and encoding of todo files
+///
+/// Files are read as UTF-8 text with `\n` line endings, whatever they
+/// were written with: a byte order mark is dropped, `\r\n` becomes `\n`
+/// and invalid byte sequences are replaced with U+FFFD. The `Format`
+/// found is used to write the text back the way the file was written.
+/// Files with invalid byte sequences are not written back, see
+/// `check_rewrite`.
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use crate::parse_error::{ParseError, ParseErrorKind};
+
+/// UTF-8 byte order mark
+const BOM: &[u8] = b"\xef\xbb\xbf";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineEnding {
+	/// `\n`, as written on unix
+	#[default]
+	Lf,
+
+	/// `\r\n`, as written on windows
+	CrLf,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+/// How a file is written
+pub struct Format {
+	pub line_ending: LineEnding,
+
+	/// true if the file starts with a UTF-8 byte order mark
+	pub bom: bool,
+}
+
+/// Text of a file and how it was written
+pub struct Decoded {
+	/// Text with `\n` line endings
+	pub text: String,
+
+	pub format: Format,
+
+	/// Invalid byte sequences found, one per line they were found on
+	pub errors: Vec<ParseError>,
+}
+
+impl Format {
+	/// Format of a file starting with `bytes`, going by its first line
+	pub fn detect(bytes: &[u8]) -> Format {
+		let line_ending = match bytes.iter().position(|b| *b == b'\n') {
+			Some(i) if i > 0 && bytes[i - 1] == b'\r' => LineEnding::CrLf,
+			_ => LineEnding::Lf,
+		};
+
+		Format {
+			line_ending,
+			bom: bytes.starts_with(BOM),
+		}
+	}
+
+	pub fn line_ending(&self) -> &'static str {
+		match self.line_ending {
+			LineEnding::Lf => "\n",
+			LineEnding::CrLf => "\r\n",
+		}
+	}
+}
+
+/// Decode the content of a file
+pub fn decode(bytes: &[u8]) -> Decoded {
+	let format = Format::detect(bytes);
+	let bytes = bytes.strip_prefix(BOM).unwrap_or(bytes);
+	let mut text = String::with_capacity(bytes.len());
+	let mut errors = Vec::new();
+
+	for (i, piece) in bytes.split_inclusive(|b| *b == b'\n').enumerate() {
+		let line = piece.strip_suffix(b"\n");
+		let content = line.map(|l| l.strip_suffix(b"\r").unwrap_or(l));
+
+		match std::str::from_utf8(content.unwrap_or(piece)) {
+			Ok(s) => text.push_str(s),
+			Err(e) => {
+				let valid = &content.unwrap_or(piece)[..e.valid_up_to()];
+				let column = String::from_utf8_lossy(valid).chars().count() + 1;
+
+				errors.push(ParseError::new(column, ParseErrorKind::InvalidUtf8).at_line(i + 1));
+				text.push_str(&String::from_utf8_lossy(content.unwrap_or(piece)));
+			}
+		}
+
+		if line.is_some() {
+			text.push('\n');
+		}
+	}
+
+	Decoded {
+		text,
+		format,
+		errors,
+	}
+}
+
+/// `text`, with `\n` line endings, encoded as `format` says
+pub fn encode(text: &str, format: Format) -> Vec<u8> {
+	let mut bytes = Vec::with_capacity(text.len() + BOM.len());
+
+	if format.bom {
+		bytes.extend_from_slice(BOM);
+	}
+
+	match format.line_ending {
+		LineEnding::Lf => bytes.extend_from_slice(text.as_bytes()),
+		LineEnding::CrLf => {
+			for piece in text.split_inclusive('\n') {
+				match piece.strip_suffix('\n') {
+					Some(line) => {
+						bytes.extend_from_slice(line.as_bytes());
+						bytes.extend_from_slice(b"\r\n");
+					}
+					None => bytes.extend_from_slice(piece.as_bytes()),
+				}
+			}
+		}
+	}
+
+	bytes
+}
+
+/// Refuse to replace `path` when `errors` were found decoding it. Its
+/// invalid bytes were read as U+FFFD, so writing the text back would
+/// lose them for good.
+pub fn check_rewrite<P: AsRef<Path>>(path: P, errors: &[ParseError]) -> Result<(), io::Error> {
+	match errors.first() {
+		None => Ok(()),
+		Some(e) => Err(io::Error::new(
+			io::ErrorKind::InvalidData,
+			format!(
+				"{}:{}:{}: invalid UTF-8, not changing the file as these bytes would be lost",
+				path.as_ref().display(),
+				e.line,
+				e.column
+			),
+		)),
+	}
+}
+
+/// Read and decode `path`. A file that does not exist yet reads as
+/// empty text.
+pub fn read_file<P: AsRef<Path>>(path: P) -> Result<Decoded, io::Error> {
+	match fs::read(path) {
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(decode(b"")),
+		Err(e) => Err(e),
+		Ok(bytes) => Ok(decode(&bytes)),
+	}
+}
+
+/// Format of `path`, going by its first line. A file that does not
+/// exist yet has the default format.
+pub fn detect_file<P: AsRef<Path>>(path: P) -> Result<Format, io::Error> {
+	let f = match File::open(path) {
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Format::default()),
+		Err(e) => return Err(e),
+		Ok(f) => f,
+	};
+	let mut first_line = Vec::new();
+
+	BufReader::new(f).read_until(b'\n', &mut first_line)?;
+
+	Ok(Format::detect(&first_line))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn decode_crlf_and_bom() {
+		let bytes = b"\xef\xbb\xbf(A) call mom due:2021-12-25\r\nbuy milk\r\n";
+		let decoded = decode(bytes);
+
+		assert_eq!(decoded.text, "(A) call mom due:2021-12-25\nbuy milk\n");
+		assert_eq!(
+			decoded.format,
+			Format {
+				line_ending: LineEnding::CrLf,
+				bom: true,
+			}
+		);
+		assert!(decoded.errors.is_empty());
+		assert_eq!(encode(&decoded.text, decoded.format), bytes.to_vec());
+	}
+
+	#[test]
+	fn decode_invalid_utf8() {
+		let decoded = decode(b"call mom\nbuy m\xffilk\nlast");
+
+		assert_eq!(decoded.text, "call mom\nbuy m\u{fffd}ilk\nlast");
+		assert_eq!(
+			decoded.errors,
+			vec![ParseError::new(6, ParseErrorKind::InvalidUtf8).at_line(2)]
+		);
+		assert_eq!(decoded.format, Format::default());
+	}
+}
@@ -0,0 +1,308 @@
+/// Optional git repository around todo.txt, see `cfg::get_git_storage`
+///
+/// The directory todo.txt is in is made a git repository and every
+/// command that changes a todo commits todo.txt and the archive files,
+/// with a message saying what happened to which todo. Only the plain
+/// `git` command is used.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::archive;
+use crate::audit::{self, Event};
+use crate::cfg::{get_archive_filename, get_todo_filename};
+use crate::todo::Todo;
+
+fn repository_dir() -> PathBuf {
+	match Path::new(&get_todo_filename()).parent() {
+		Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+		_ => PathBuf::from("."),
+	}
+}
+
+/// Run git in the repository, returning what it printed. Err with what
+/// it printed on stderr if it fails.
+fn git(args: &[&str]) -> Result<String, io::Error> {
+	let output = Command::new("git")
+		.arg("-C")
+		.arg(repository_dir())
+		.args(args)
+		.output()?;
+
+	if !output.status.success() {
+		return Err(io::Error::other(format!(
+			"git {}: {}",
+			args.join(" "),
+			String::from_utf8_lossy(&output.stderr).trim()
+		)));
+	}
+
+	Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// `args` of a command that commits, with an identity to commit as if
+/// none is configured. git refuses to commit without one.
+fn with_identity(mut args: Vec<&str>) -> Vec<&str> {
+	if git(&["config", "user.email"]).is_err() {
+		args.splice(
+			0..0,
+			[
+				"-c",
+				"user.name=todo-txt",
+				"-c",
+				"user.email=todo-txt@localhost",
+			],
+		);
+	}
+
+	args
+}
+
+/// Create the repository unless the directory of todo.txt is the top
+/// of one. A directory inside the work tree of another repository, such
+/// as a project the data path was put in, gets its own so that todo
+/// commits are not mixed with the project's.
+fn init_repository() -> Result<(), io::Error> {
+	let dir = repository_dir();
+	let dir = fs::canonicalize(&dir).unwrap_or(dir);
+	let is_top = match git(&["rev-parse", "--show-toplevel"]) {
+		Ok(top) => fs::canonicalize(top.trim()).is_ok_and(|top| top == dir),
+		Err(_) => false,
+	};
+
+	if !is_top {
+		git(&["init", "-q"])?;
+	}
+
+	Ok(())
+}
+
+/// What happened to a todo, such as `completed: call mom`
+fn describe(event: &Event) -> String {
+	let line = event.after.as_ref().or(event.before.as_ref());
+	let task = match line.map(|l| l.parse::<Todo>()) {
+		Some(Ok(todo)) => todo.task(),
+		_ => line.cloned().unwrap_or_default(),
+	};
+
+	format!("{}: {}", event.action.as_str(), task)
+}
+
+/// Commit message for `events`, all made by the same command
+fn message(events: &[Event]) -> String {
+	let command = events.first().map(|e| e.command.as_str()).unwrap_or("");
+	let subject = match events {
+		[event] => describe(event),
+		[first, ..] if events.iter().all(|e| e.action == first.action) => {
+			format!("{}: {} todos", first.action.as_str(), events.len())
+		}
+		_ => command.to_string(),
+	};
+	let lines: Vec<String> = events.iter().map(describe).collect();
+
+	format!(
+		"{}\n\ntodo-txt {}\n\n{}\n",
+		subject,
+		command,
+		lines.join("\n")
+	)
+}
+
+/// true if `path`, in the repository directory, is todo.txt or one of
+/// the archive files
+fn is_todo_file(path: &Path) -> bool {
+	path == Path::new(&get_todo_filename())
+		|| archive::is_archive_file(&get_archive_filename(), path)
+}
+
+/// todo.txt and the archive files there are, and those already committed
+/// that are gone, such as archive files emptied by unarchive
+fn todo_files() -> Result<Vec<String>, io::Error> {
+	let dir = repository_dir();
+	let mut files = vec![PathBuf::from(get_todo_filename())];
+
+	files.extend(
+		archive::list_files(&get_archive_filename())?
+			.into_iter()
+			.map(|f| f.path),
+	);
+	files.retain(|f| f.exists());
+
+	for tracked in git(&["ls-files", "-z"])?
+		.split('\0')
+		.filter(|t| !t.is_empty())
+	{
+		let path = dir.join(tracked);
+
+		if !path.exists() && is_todo_file(&path) {
+			files.push(path);
+		}
+	}
+
+	Ok(files
+		.iter()
+		.map(|f| f.to_string_lossy().to_string())
+		.collect())
+}
+
+/// `args`, followed by `--` and `files`
+fn with_files<'a>(mut args: Vec<&'a str>, files: &'a [String]) -> Vec<&'a str> {
+	args.push("--");
+	args.extend(files.iter().map(String::as_str));
+	args
+}
+
+/// Commit todo.txt and the archive files, if this process changed them.
+/// Other files are left out of the commit, even if they are staged.
+pub fn commit_changes() -> Result<(), io::Error> {
+	let events = audit::logged();
+
+	if events.is_empty() {
+		return Ok(());
+	}
+
+	init_repository()?;
+
+	let files = todo_files()?;
+
+	if files.is_empty() {
+		return Ok(());
+	}
+
+	git(&with_files(vec!["add", "-A"], &files))?;
+
+	if git(&with_files(vec!["diff", "--cached", "--quiet"], &files)).is_ok() {
+		return Ok(());
+	}
+
+	let message = message(&events);
+
+	git(&with_identity(with_files(
+		vec!["commit", "-q", "-m", &message, "--only"],
+		&files,
+	)))
+	.map(|_| ())
+}
+
+/// The last `count` commits that changed todo.txt or the archive, newest
+/// first: short hash, date and subject
+pub fn log(count: usize) -> Result<String, io::Error> {
+	init_repository()?;
+
+	let count = count.to_string();
+	let todo_filename = get_todo_filename();
+	let mut files = todo_files()?;
+
+	// todo.txt is looked for in the history even if it is gone now
+	if !files.contains(&todo_filename) {
+		files.push(todo_filename);
+	}
+
+	git(&with_files(
+		vec![
+			"log",
+			"-n",
+			&count,
+			"--date=format:%Y-%m-%d %H:%M",
+			"--format=%h %ad %s",
+		],
+		&files,
+	))
+	.or_else(|e| {
+		// A repository without any commit yet has no log
+		match git(&["rev-parse", "--verify", "-q", "HEAD"]) {
+			Err(_) => Ok(String::new()),
+			Ok(_) => Err(e),
+		}
+	})
+}
+
+/// Make a commit undoing `commit`. If it can not be undone cleanly, the
+/// files are left as they were. Commits that changed other files than
+/// todo.txt and the archive files are refused.
+pub fn revert(commit: &str) -> Result<(), io::Error> {
+	init_repository()?;
+
+	// Paths are relative to the repository directory with --relative,
+	// which leaves out those outside of it
+	let changed = |relative| {
+		let mut args = vec![
+			"diff-tree",
+			"-r",
+			"--root",
+			"--no-commit-id",
+			"--name-only",
+			"-z",
+		];
+
+		if relative {
+			args.push("--relative");
+		}
+
+		args.push(commit);
+		git(&args)
+	};
+	let all = changed(false)?;
+	let in_dir = changed(true)?;
+	let in_dir: Vec<&str> = in_dir.split('\0').filter(|p| !p.is_empty()).collect();
+	let only_todo_files = !in_dir.is_empty()
+		&& all.split('\0').filter(|p| !p.is_empty()).count() == in_dir.len()
+		&& in_dir
+			.iter()
+			.all(|p| is_todo_file(&repository_dir().join(p)));
+
+	if !only_todo_files {
+		return Err(io::Error::other(
+			"it did not only change todo.txt and the archive",
+		));
+	}
+
+	if let Err(e) = git(&with_identity(vec!["revert", "--no-edit", commit])) {
+		let _ = git(&["revert", "--abort"]);
+
+		return Err(e);
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::audit::Action;
+	use crate::journal::JournalFile;
+	use chrono::Local;
+
+	fn event(action: Action, line: &str) -> Event {
+		Event {
+			time: Local::now(),
+			command: String::from("do 1 2"),
+			action,
+			file: JournalFile::Todo,
+			id: None,
+			before: None,
+			after: Some(line.to_string()),
+		}
+	}
+
+	#[test]
+	fn commit_messages() {
+		assert_eq!(
+			message(&[event(Action::Completed, "x call mom due:2021-12-25")]),
+			"completed: call mom\n\ntodo-txt do 1 2\n\ncompleted: call mom\n"
+		);
+		assert_eq!(
+			message(&[
+				event(Action::Completed, "x call mom"),
+				event(Action::Completed, "x buy milk"),
+			]),
+			"completed: 2 todos\n\ntodo-txt do 1 2\n\ncompleted: call mom\ncompleted: buy milk\n"
+		);
+		assert!(message(&[
+			event(Action::Completed, "x call mom"),
+			event(Action::Created, "call mom"),
+		])
+		.starts_with("do 1 2\n\n"));
+	}
+}
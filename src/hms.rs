@@ -5,27 +5,34 @@ lazy_static! {
 		Regex::new(r"^((?P<hours>\d+)h)?((?P<minutes>\d+)m)?((?P<seconds>\d+)s)?$").unwrap();
 }
 
-/// Convert an XhXmXs string to seconds
-pub fn to_seconds(hms: &String) -> i64 {
+/// Convert an XhXmXs string to seconds, None if `hms` is not one
+pub fn parse(hms: &str) -> Option<i64> {
+	if hms.is_empty() {
+		return None;
+	}
+
+	let m = HMS_RS.captures(hms)?;
 	let mut total_seconds: i64 = 0;
-	let matches = HMS_RS.captures(&hms);
 
-	if let Some(m) = matches {
-		match m.name("hours") {
-			Some(v) => total_seconds += v.as_str().parse::<i64>().unwrap() * 3600,
-			None => (),
-		}
-		match m.name("minutes") {
-			Some(v) => total_seconds += v.as_str().parse::<i64>().unwrap() * 60,
-			None => (),
-		}
-		match m.name("seconds") {
-			Some(v) => total_seconds += v.as_str().parse::<i64>().unwrap(),
-			None => (),
+	for (name, multiplier) in [("hours", 3600), ("minutes", 60), ("seconds", 1)] {
+		if let Some(v) = m.name(name) {
+			let seconds = v.as_str().parse::<i64>().ok()?.checked_mul(multiplier)?;
+			total_seconds = total_seconds.checked_add(seconds)?;
 		}
 	}
 
-	return total_seconds;
+	// Keep within what chrono::Duration can represent
+	if total_seconds > i64::MAX / 1000 {
+		return None;
+	}
+
+	Some(total_seconds)
+}
+
+/// Convert an XhXmXs string to seconds, 0 if `hms` is not one
+#[allow(clippy::ptr_arg)]
+pub fn to_seconds(hms: &String) -> i64 {
+	parse(hms).unwrap_or(0)
 }
 
 /// Convert seconds to an XhXmXs string
@@ -48,7 +55,7 @@ pub fn from_seconds(seconds: i64) -> String {
 		parts.push(format!("{}s", seconds));
 	}
 
-	return parts.join("");
+	parts.join("")
 }
 
 #[cfg(test)]
@@ -73,6 +80,15 @@ mod tests {
 		assert_eq!(seconds, 1);
 	}
 
+	#[test]
+	fn parse_invalid() {
+		assert_eq!(parse(""), None);
+		assert_eq!(parse("1x"), None);
+		assert_eq!(parse("1m1h"), None);
+		assert_eq!(parse("99999999999999999999h"), None);
+		assert_eq!(parse("9999999999999999h"), None);
+	}
+
 	#[test]
 	fn from_seconds_hms() {
 		let result = from_seconds(3661);
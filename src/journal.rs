@@ -0,0 +1,518 @@
+/// Journal of the lines each command changed in todo.txt and archive.txt,
+/// so commands can be undone and redone
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::prelude::Write;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+use crate::audit;
+use crate::cfg::{
+	get_archive_filename, get_journal_size, get_list_data_filename, get_todo_filename,
+};
+use crate::todo::Todo;
+use crate::todo_file::{read_default_text, write_default_text, write_file_atomically};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// File a change was made to
+pub enum JournalFile {
+	Todo,
+	Archive,
+}
+
+impl JournalFile {
+	/// The user's default file of this kind
+	pub fn filename(&self) -> String {
+		match self {
+			JournalFile::Todo => get_todo_filename(),
+			JournalFile::Archive => get_archive_filename(),
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// One line changed by a command
+///
+/// `before` is None for lines that were added and `after` is None for
+/// lines that were removed.
+pub struct Change {
+	/// Operation (one run of a command) the change is part of
+	pub operation: Uuid,
+
+	pub time: DateTime<Local>,
+
+	/// Command line of the operation
+	pub command: String,
+
+	pub file: JournalFile,
+
+	/// Id of the todo on the line, if it has one
+	pub id: Option<Uuid>,
+
+	/// Line the change was made at, starting at 0. None for appends.
+	pub index: Option<usize>,
+
+	pub before: Option<String>,
+
+	pub after: Option<String>,
+}
+
+struct Operation {
+	id: Uuid,
+	time: DateTime<Local>,
+	command: String,
+}
+
+lazy_static! {
+	static ref OPERATION: Mutex<Operation> = Mutex::new(Operation {
+		id: Uuid::new_v4(),
+		time: Local::now(),
+		command: String::new(),
+	});
+}
+
+/// Name the operation changes of this process are recorded under,
+/// usually the command line
+pub fn set_command(command: &str) {
+	if let Ok(mut op) = OPERATION.lock() {
+		op.command = command.to_string();
+	}
+}
+
+fn journal_filename() -> String {
+	get_list_data_filename("journal.jsonl")
+}
+
+fn redo_filename() -> String {
+	get_list_data_filename("redo.jsonl")
+}
+
+fn new_change(
+	file: JournalFile,
+	index: Option<usize>,
+	before: Option<&str>,
+	after: Option<&str>,
+) -> Change {
+	let id = after.or(before).and_then(line_id);
+	let (operation, time, command) = match OPERATION.lock() {
+		Ok(op) => (op.id, op.time, op.command.clone()),
+		Err(_) => (Uuid::new_v4(), Local::now(), String::new()),
+	};
+
+	Change {
+		operation,
+		time,
+		command,
+		file,
+		id,
+		index,
+		before: before.map(String::from),
+		after: after.map(String::from),
+	}
+}
+
+/// Id of the todo on `line`, derived from its text when it does not
+/// store one (see `Todo::id_text`)
+pub(crate) fn line_id(line: &str) -> Option<Uuid> {
+	line.parse::<Todo>().ok().map(|todo| todo.id)
+}
+
+/// Id written on `line`, if any
+pub(crate) fn written_id(line: &str) -> Option<Uuid> {
+	let todo = line.parse::<Todo>().ok()?;
+
+	todo.id_text().is_none().then_some(todo.id)
+}
+
+/// Lines of `lines` (with their index) that are not in `other`, counted
+/// as a multiset of lines
+fn only_in<'a>(lines: &[&'a str], other: &mut HashMap<&str, usize>) -> Vec<(usize, &'a str)> {
+	let mut result = Vec::new();
+
+	for (i, l) in lines.iter().enumerate() {
+		match other.get_mut(*l) {
+			Some(n) if *n > 0 => *n -= 1,
+			_ => result.push((i, *l)),
+		}
+	}
+
+	result
+}
+
+/// Changes that turn the lines of `before` into the lines of `after`.
+/// Lines that are not identical are matched by the id written on them.
+pub fn diff(file: JournalFile, before: &str, after: &str) -> Vec<Change> {
+	let old: Vec<&str> = before.lines().collect();
+	let new: Vec<&str> = after.lines().collect();
+
+	let mut old_counts: HashMap<&str, usize> = HashMap::new();
+	let mut new_counts: HashMap<&str, usize> = HashMap::new();
+
+	for l in &old {
+		*old_counts.entry(l).or_default() += 1;
+	}
+
+	for l in &new {
+		*new_counts.entry(l).or_default() += 1;
+	}
+
+	let removed = only_in(&old, &mut new_counts);
+	let added = only_in(&new, &mut old_counts);
+
+	let mut removed_by_id: HashMap<Uuid, usize> = HashMap::new();
+
+	for (i, (_, l)) in removed.iter().enumerate() {
+		if let Some(id) = line_id(l) {
+			removed_by_id.entry(id).or_insert(i);
+		}
+	}
+
+	let mut modified = vec![false; removed.len()];
+	let mut changes = Vec::new();
+
+	for (index, l) in added {
+		match line_id(l).and_then(|id| removed_by_id.remove(&id)) {
+			Some(i) => {
+				modified[i] = true;
+				changes.push(new_change(file, Some(index), Some(removed[i].1), Some(l)));
+			}
+			None => changes.push(new_change(file, Some(index), None, Some(l))),
+		}
+	}
+
+	for (i, (index, l)) in removed.into_iter().enumerate() {
+		if !modified[i] {
+			changes.push(new_change(file, Some(index), Some(l), None));
+		}
+	}
+
+	changes
+}
+
+fn read_changes(filename: &str) -> Result<Vec<Change>, io::Error> {
+	let text = match fs::read_to_string(filename) {
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+		Err(e) => return Err(e),
+		Ok(text) => text,
+	};
+
+	text.lines()
+		.filter(|l| !l.trim().is_empty())
+		.map(|l| serde_json::from_str(l).map_err(io::Error::from))
+		.collect()
+}
+
+fn write_changes(filename: &str, changes: &[Change]) -> Result<(), io::Error> {
+	write_file_atomically(filename, |w| {
+		for c in changes {
+			serde_json::to_writer(&mut *w, c)?;
+			w.write_all(b"\n")?;
+		}
+
+		Ok(())
+	})
+}
+
+/// Remove the last `count` operations from `changes`, newest first
+fn pop_operations(changes: &mut Vec<Change>, count: usize) -> Vec<Vec<Change>> {
+	let mut operations: Vec<Vec<Change>> = Vec::new();
+
+	while let Some(c) = changes.last() {
+		let is_new = operations
+			.last()
+			.is_none_or(|op| op[0].operation != c.operation);
+
+		if is_new && operations.len() == count {
+			break;
+		}
+
+		let c = changes.pop().unwrap();
+
+		match operations.last_mut() {
+			Some(op) if !is_new => op.insert(0, c),
+			_ => operations.push(vec![c]),
+		}
+	}
+
+	operations
+}
+
+/// Record `changes` in the journal and the audit log. Recording a new
+/// operation clears the operations that could be redone.
+pub fn record(changes: Vec<Change>) -> Result<(), io::Error> {
+	audit::log(&changes)?;
+
+	let size = get_journal_size();
+
+	if size == 0 || changes.is_empty() {
+		return Ok(());
+	}
+
+	let mut journal = read_changes(&journal_filename())?;
+	journal.extend(changes);
+
+	let mut kept = pop_operations(&mut journal, size);
+	kept.reverse();
+	write_changes(&journal_filename(), &kept.concat())?;
+
+	match fs::remove_file(redo_filename()) {
+		Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+		_ => Ok(()),
+	}
+}
+
+/// Record the difference between the old and new content of a file
+pub fn record_rewrite(file: JournalFile, before: &str, after: &str) -> Result<(), io::Error> {
+	record(diff(file, before, after))
+}
+
+/// Record lines appended to a file
+pub fn record_append(file: JournalFile, lines: &[String]) -> Result<(), io::Error> {
+	record(
+		lines
+			.iter()
+			.map(|l| new_change(file, None, None, Some(l)))
+			.collect(),
+	)
+}
+
+fn changed_since(line: &str) -> io::Error {
+	io::Error::new(
+		io::ErrorKind::InvalidData,
+		format!("file changed since, could not find line '{}'", line),
+	)
+}
+
+fn position(lines: &[String], line: &str) -> Result<usize, io::Error> {
+	lines
+		.iter()
+		.position(|l| l == line)
+		.ok_or_else(|| changed_since(line))
+}
+
+/// Reverse the changes of one operation made to `lines`
+fn undo_lines(lines: &mut Vec<String>, changes: &[&Change]) -> Result<(), io::Error> {
+	for c in changes.iter().rev() {
+		if let Some(after) = &c.after {
+			let i = position(lines, after)?;
+
+			match &c.before {
+				Some(before) => lines[i] = before.clone(),
+				None => {
+					lines.remove(i);
+				}
+			}
+		}
+	}
+
+	let mut removed: Vec<&&Change> = changes.iter().filter(|c| c.after.is_none()).collect();
+	removed.sort_by_key(|c| c.index);
+
+	for c in removed {
+		let i = c.index.unwrap_or(lines.len()).min(lines.len());
+		lines.insert(i, c.before.clone().unwrap_or_default());
+	}
+
+	Ok(())
+}
+
+/// Make the changes of one operation to `lines` again
+fn redo_lines(lines: &mut Vec<String>, changes: &[&Change]) -> Result<(), io::Error> {
+	for c in changes {
+		if let Some(before) = &c.before {
+			let i = position(lines, before)?;
+
+			match &c.after {
+				Some(after) => lines[i] = after.clone(),
+				None => {
+					lines.remove(i);
+				}
+			}
+		}
+	}
+
+	let mut added: Vec<&&Change> = changes.iter().filter(|c| c.before.is_none()).collect();
+	added.sort_by_key(|c| c.index.unwrap_or(usize::MAX));
+
+	for c in added {
+		let i = c.index.unwrap_or(lines.len()).min(lines.len());
+		lines.insert(i, c.after.clone().unwrap_or_default());
+	}
+
+	Ok(())
+}
+
+/// Undo (`forward` false) or redo `operations` in order, on every file
+/// they changed. Nothing is written if any of them can not be applied.
+fn apply(operations: &[Vec<Change>], forward: bool) -> Result<(), io::Error> {
+	let mut results = Vec::new();
+
+	for file in [JournalFile::Todo, JournalFile::Archive] {
+		if !operations.iter().flatten().any(|c| c.file == file) {
+			continue;
+		}
+
+		let filename = file.filename();
+		let mut lines: Vec<String> = read_default_text(file)?.lines().map(String::from).collect();
+
+		for op in operations {
+			let changes: Vec<&Change> = op.iter().filter(|c| c.file == file).collect();
+
+			if forward {
+				redo_lines(&mut lines, &changes)
+			} else {
+				undo_lines(&mut lines, &changes)
+			}
+			.map_err(|e| io::Error::new(e.kind(), format!("{}: {}", filename, e)))?;
+		}
+
+		results.push((file, lines));
+	}
+
+	for (file, lines) in results {
+		let text: String = lines.iter().map(|l| format!("{}\n", l)).collect();
+
+		write_default_text(file, &text)?;
+	}
+
+	Ok(())
+}
+
+/// Log the changes made by undoing (`forward` false) or redoing
+/// `operations` to the audit log, as changes of the current operation
+fn audit_applied(operations: &[Vec<Change>], forward: bool) -> Result<(), io::Error> {
+	let changes: Vec<Change> = operations
+		.iter()
+		.flatten()
+		.map(|c| {
+			let (before, after) = match forward {
+				true => (&c.before, &c.after),
+				false => (&c.after, &c.before),
+			};
+
+			new_change(c.file, c.index, before.as_deref(), after.as_deref())
+		})
+		.collect();
+
+	audit::log(&changes)
+}
+
+/// Undo the last `count` operations, newest first. Returns the
+/// operations undone, which can then be redone.
+pub fn undo(count: usize) -> Result<Vec<Vec<Change>>, io::Error> {
+	let mut journal = read_changes(&journal_filename())?;
+	let operations = pop_operations(&mut journal, count);
+
+	apply(&operations, false)?;
+	audit_applied(&operations, false)?;
+
+	let mut redo = read_changes(&redo_filename())?;
+	redo.extend(operations.iter().flatten().cloned());
+
+	write_changes(&redo_filename(), &redo)?;
+	write_changes(&journal_filename(), &journal)?;
+
+	Ok(operations)
+}
+
+/// Redo the last `count` undone operations, in the order they were
+/// first made. Returns the operations redone.
+pub fn redo(count: usize) -> Result<Vec<Vec<Change>>, io::Error> {
+	let mut redo = read_changes(&redo_filename())?;
+	let operations = pop_operations(&mut redo, count);
+
+	apply(&operations, true)?;
+	audit_applied(&operations, true)?;
+
+	let mut journal = read_changes(&journal_filename())?;
+	journal.extend(operations.iter().flatten().cloned());
+
+	write_changes(&journal_filename(), &journal)?;
+	write_changes(&redo_filename(), &redo)?;
+
+	Ok(operations)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const ID: &str = "id:2a6a3d2e-4c0a-4c6e-9d3b-7a1f3b8d7c11";
+
+	fn lines(text: &str) -> Vec<String> {
+		text.lines().map(String::from).collect()
+	}
+
+	#[test]
+	fn diff_matches_lines_by_id() {
+		let before = format!("first\nsecond {}\nthird\n", ID);
+		let after = format!("first\n(A) second {}\nfourth\n", ID);
+		let changes = diff(JournalFile::Todo, &before, &after);
+
+		let summary: Vec<(Option<usize>, Option<&str>, Option<&str>)> = changes
+			.iter()
+			.map(|c| (c.index, c.before.as_deref(), c.after.as_deref()))
+			.collect();
+
+		assert_eq!(
+			summary,
+			vec![
+				(
+					Some(1),
+					Some(&format!("second {}", ID)[..]),
+					Some(&format!("(A) second {}", ID)[..])
+				),
+				(Some(2), None, Some("fourth")),
+				(Some(2), Some("third"), None),
+			]
+		);
+		assert!(changes[0].id.is_some());
+	}
+
+	#[test]
+	fn undo_and_redo_lines() {
+		let before = format!("first\nsecond {}\nthird\n", ID);
+		let after = format!("(A) second {}\nfourth\n", ID);
+		let changes = diff(JournalFile::Todo, &before, &after);
+		let changes: Vec<&Change> = changes.iter().collect();
+
+		let mut l = lines(&after);
+		undo_lines(&mut l, &changes).unwrap();
+		assert_eq!(l, lines(&before));
+
+		redo_lines(&mut l, &changes).unwrap();
+		assert_eq!(l, lines(&after));
+	}
+
+	#[test]
+	fn undo_fails_if_line_changed() {
+		let changes = diff(JournalFile::Todo, "first\n", "second\n");
+		let changes: Vec<&Change> = changes.iter().collect();
+		let mut l = lines("something else\n");
+
+		assert!(undo_lines(&mut l, &changes).is_err());
+		assert_eq!(l, lines("something else\n"));
+	}
+
+	#[test]
+	fn pop_operations_groups_changes() {
+		let mut changes = diff(JournalFile::Todo, "", "a\nb\n");
+		let mut other = changes[0].clone();
+		other.operation = Uuid::new_v4();
+		changes.push(other);
+
+		let ops = pop_operations(&mut changes, 1);
+		assert_eq!(ops.len(), 1);
+		assert_eq!(ops[0].len(), 1);
+		assert_eq!(changes.len(), 2);
+
+		let ops = pop_operations(&mut changes, 5);
+		assert_eq!(ops.len(), 1);
+		assert_eq!(ops[0].len(), 2);
+		assert!(changes.is_empty());
+	}
+}
@@ -0,0 +1,47 @@
+//! Read, query, modify and write [todo.txt](http://todotxt.org/) files.
+//!
+//! This crate holds everything the `todo-txt` binary is built on: the
+//! line parser ([`Todo`]), list queries ([`TodoList`]), reading and
+//! writing files ([`todo_file`]) and the configuration those files are
+//! located with ([`cfg`]).
+//!
+//! ```
+//! use todo_txt::{todo_file, Todo};
+//!
+//! let text = "(A) Call mom +Family @phone due:2021-12-25\nx Buy milk\n";
+//! let list = todo_file::read_todos(text.as_bytes()).unwrap();
+//!
+//! let family = list.filter_by_project("Family");
+//! assert_eq!(family.items.len(), 1);
+//!
+//! let mut todo: Todo = "Write report".parse().unwrap();
+//! todo.priority = Some('B');
+//! assert!(todo.serialize().starts_with("(B) Write report"));
+//! ```
+
+#[macro_use]
+extern crate lazy_static;
+
+pub mod archive;
+pub mod audit;
+pub mod backup;
+pub mod cfg;
+pub mod encoding;
+pub mod git;
+pub mod hms;
+pub mod journal;
+pub mod merge;
+pub mod parse_error;
+pub mod query;
+pub mod repeat_time;
+pub mod todo;
+pub mod todo_file;
+pub mod todo_list;
+pub mod token;
+
+#[cfg(test)]
+mod test_util;
+
+pub use parse_error::ParseError;
+pub use todo::Todo;
+pub use todo_list::TodoList;
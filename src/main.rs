@@ -1,33 +1,34 @@
 #[macro_use]
 extern crate lazy_static;
-extern crate chrono;
-extern crate config;
-extern crate dirs;
 extern crate gumdrop;
-extern crate regex;
 extern crate termcolor;
+extern crate todo_txt;
 
 use gumdrop::Options;
 use std::error::Error;
+use std::path::Path;
+use todo_txt::{cfg, git, journal, todo_file};
 
-// specific modules for todo-txt
-mod cfg;
+// commands of the todo-txt binary
 mod cmd_add;
 mod cmd_archive;
+mod cmd_backup;
+mod cmd_check;
 mod cmd_clock;
 mod cmd_do;
+mod cmd_history;
+mod cmd_init;
+mod cmd_log;
 mod cmd_ls;
+mod cmd_merge;
 mod cmd_pri;
+mod cmd_redo;
 mod cmd_report;
+mod cmd_revert;
 mod cmd_rm;
 mod cmd_tag;
-mod todo;
-mod todo_file;
-mod todo_list;
-
-// generic "helper" modules
-mod hms;
-mod repeat_time;
+mod cmd_unarchive;
+mod cmd_undo;
 
 #[derive(Debug, Options)]
 struct MyOptions {
@@ -40,12 +41,18 @@ struct MyOptions {
 	#[options(help = "Verbose output")]
 	verbose: bool,
 
+	#[options(help = "Work on a list of the [lists] configuration table")]
+	list: String,
+
 	#[options(command)]
 	command: Option<Command>,
 }
 
 #[derive(Debug, Options)]
 enum Command {
+	#[options(help = "Create the configuration file, todo.txt and archive.txt")]
+	Init(cmd_init::Opts),
+
 	#[options(help = "List todos")]
 	Ls(cmd_ls::Opts),
 
@@ -55,6 +62,9 @@ enum Command {
 	#[options(help = "Archive completed todos")]
 	Archive(cmd_archive::Opts),
 
+	#[options(help = "Move archived todos back into todo.txt")]
+	Unarchive(cmd_unarchive::Opts),
+
 	#[options(help = "Mark a todo as done")]
 	Do(cmd_do::Opts),
 
@@ -72,6 +82,30 @@ enum Command {
 
 	#[options(help = "Report on todo tasks and projects")]
 	Report(cmd_report::Opts),
+
+	#[options(help = "Check todo.txt for errors")]
+	Check(cmd_check::Opts),
+
+	#[options(help = "List or restore snapshots of todo.txt")]
+	Backup(cmd_backup::Opts),
+
+	#[options(help = "Undo the last command")]
+	Undo(cmd_undo::Opts),
+
+	#[options(help = "Redo the last undone command")]
+	Redo(cmd_redo::Opts),
+
+	#[options(help = "Show everything that happened to a todo")]
+	History(cmd_history::Opts),
+
+	#[options(help = "Merge two changed copies of a todo file, as a git merge driver")]
+	Merge(cmd_merge::Opts),
+
+	#[options(help = "Show the commits of git_storage")]
+	Log(cmd_log::Opts),
+
+	#[options(help = "Revert a commit of git_storage")]
+	Revert(cmd_revert::Opts),
 }
 
 fn usage() {
@@ -86,17 +120,42 @@ fn usage() {
 
 fn try_main() -> Result<(), Box<dyn Error>> {
 	let opts = MyOptions::parse_args_default_or_exit();
-	let config_file = if opts.config.len() > 0 {
+	let config_file = if !opts.config.is_empty() {
 		Some(opts.config.as_str())
 	} else {
 		None
 	};
 
-	cfg::read_config(config_file)?;
+	// init creates the configuration file, so it may not exist yet
+	let config_missing = config_file.is_some_and(|f| !Path::new(f).exists());
+
+	if !(config_missing && matches!(opts.command, Some(Command::Init(_)))) {
+		cfg::read_config(config_file)?;
+	}
+
+	let list = match opts.list.is_empty() {
+		true => cfg::check_default_list(),
+		false => cfg::set_list(&opts.list),
+	};
+
+	if let Err(e) = list {
+		eprintln!("{}", e);
+		std::process::exit(1);
+	}
+
+	// Record the command line from the command name on, leaving out
+	// global options such as --config
+	if let Some(name) = opts.command_name() {
+		let args: Vec<String> = std::env::args().skip(1).skip_while(|a| a != name).collect();
+
+		journal::set_command(&args.join(" "));
+	}
 
 	match opts.command {
+		Some(Command::Init(copts)) => cmd_init::execute(&copts, config_file),
 		Some(Command::Add(copts)) => cmd_add::execute(&copts),
 		Some(Command::Archive(copts)) => cmd_archive::execute(&copts),
+		Some(Command::Unarchive(copts)) => cmd_unarchive::execute(&copts),
 		Some(Command::Do(copts)) => cmd_do::execute(&copts),
 		Some(Command::Ls(copts)) => cmd_ls::execute(&copts),
 		Some(Command::Rm(copts)) => cmd_rm::execute(&copts),
@@ -104,6 +163,14 @@ fn try_main() -> Result<(), Box<dyn Error>> {
 		Some(Command::Pri(copts)) => cmd_pri::execute(&copts),
 		Some(Command::Clock(copts)) => cmd_clock::execute(&copts),
 		Some(Command::Report(copts)) => cmd_report::execute(&copts),
+		Some(Command::Check(copts)) => cmd_check::execute(&copts),
+		Some(Command::Backup(copts)) => cmd_backup::execute(&copts),
+		Some(Command::Undo(copts)) => cmd_undo::execute(&copts),
+		Some(Command::Redo(copts)) => cmd_redo::execute(&copts),
+		Some(Command::History(copts)) => cmd_history::execute(&copts),
+		Some(Command::Merge(copts)) => cmd_merge::execute(&copts),
+		Some(Command::Log(copts)) => cmd_log::execute(&copts),
+		Some(Command::Revert(copts)) => cmd_revert::execute(&copts),
 		_ => {
 			if cfg::get_auto_ls() {
 				let copts = cmd_ls::default_opts();
@@ -115,6 +182,14 @@ fn try_main() -> Result<(), Box<dyn Error>> {
 		}
 	}
 
+	if cfg::get_git_storage() {
+		let _lock = todo_file::lock_default_file()?;
+
+		if let Err(e) = git::commit_changes() {
+			eprintln!("warning: could not commit the changes, {}", e);
+		}
+	}
+
 	Ok(())
 }
 
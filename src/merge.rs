@@ -0,0 +1,432 @@
+/// Three-way merge of todo.txt files, line by line per todo id
+///
+/// Lines are matched across the files by their id, lines without one by
+/// their text. Each line is then merged field by field: the completion,
+/// dates, priority, task text and every key value attribute. A field
+/// changed on one side only takes that change, `clocked:` time added on
+/// both sides is added up, and anything else changed on both sides is a
+/// conflict.
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use uuid::Uuid;
+
+use crate::hms;
+use crate::journal::written_id;
+use crate::todo::Todo;
+
+/// Line of a merged file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Merged {
+	Line(String),
+
+	/// A line both sides changed in ways that do not merge, with the
+	/// fields they disagree on. A side that removed the line has None.
+	Conflict {
+		ours: Option<String>,
+		theirs: Option<String>,
+		fields: Vec<String>,
+	},
+}
+
+/// How a line is matched across the three files
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Key {
+	Id(Uuid),
+
+	/// Text of a line without an id, and which occurrence of it it is
+	Text(String, usize),
+}
+
+/// Non empty lines of `text` by their key, in order
+fn keyed_lines(text: &str) -> Vec<(Key, &str)> {
+	let mut occurrences: HashMap<&str, usize> = HashMap::new();
+
+	text.lines()
+		.filter(|l| !l.trim().is_empty())
+		.map(|l| match written_id(l) {
+			Some(id) => (Key::Id(id), l),
+			None => {
+				let n = occurrences.entry(l).or_default();
+				*n += 1;
+
+				(Key::Text(l.to_string(), *n), l)
+			}
+		})
+		.collect()
+}
+
+/// Fields of a todo, by name. A key repeated in the todo is one field
+/// with all its values, separated by spaces.
+fn fields(todo: &Todo) -> BTreeMap<String, String> {
+	let mut fields = BTreeMap::new();
+	let date = |d: chrono::NaiveDate| d.format("%Y-%m-%d").to_string();
+
+	if todo.is_complete {
+		fields.insert(String::from("completion"), String::from("x"));
+	}
+
+	if let Some(d) = todo.completed_at {
+		fields.insert(String::from("completion date"), date(d));
+	}
+
+	if let Some(d) = todo.created_at {
+		fields.insert(String::from("creation date"), date(d));
+	}
+
+	if let Some(p) = todo.priority {
+		fields.insert(String::from("priority"), p.to_string());
+	}
+
+	fields.insert(String::from("task"), todo.task());
+
+	for (k, v) in todo.key_values() {
+		fields
+			.entry(format!("{}:", k))
+			.and_modify(|values: &mut String| {
+				values.push(' ');
+				values.push_str(v);
+			})
+			.or_insert_with(|| v.to_string());
+	}
+
+	fields
+}
+
+/// Three-way merge of one value. None if both sides changed it, each in
+/// their own way.
+fn merge_value<T: PartialEq>(base: T, ours: T, theirs: T) -> Option<T> {
+	if ours == theirs || theirs == base {
+		Some(ours)
+	} else if ours == base {
+		Some(theirs)
+	} else {
+		None
+	}
+}
+
+/// Time clocked on both sides added up, None if a value is not a time
+fn merge_clocked(base: Option<&str>, ours: &str, theirs: &str) -> Option<String> {
+	let base = base.map_or(Some(0), hms::parse)?;
+	let added = hms::parse(ours)? - base + hms::parse(theirs)? - base;
+
+	Some(hms::from_seconds(base + added))
+}
+
+/// Merge the todos `ours` and `theirs` changed from `base` field by
+/// field. Err with the names of the fields they disagree on.
+fn merge_todos(base: Option<&Todo>, ours: &Todo, theirs: &Todo) -> Result<Todo, Vec<String>> {
+	let base_fields = base.map(fields).unwrap_or_default();
+	let our_fields = fields(ours);
+	let their_fields = fields(theirs);
+
+	let names: BTreeSet<&String> = base_fields
+		.keys()
+		.chain(our_fields.keys())
+		.chain(their_fields.keys())
+		.collect();
+
+	let mut merged = BTreeMap::new();
+	let mut conflicts = Vec::new();
+
+	for name in names {
+		let (b, o, t) = (
+			base_fields.get(name),
+			our_fields.get(name),
+			their_fields.get(name),
+		);
+		let value = match (merge_value(b, o, t), o, t) {
+			(Some(v), _, _) => Some(v.cloned()),
+			(None, Some(o), Some(t)) if name == "clocked:" => {
+				merge_clocked(b.map(String::as_str), o, t).map(Some)
+			}
+			_ => None,
+		};
+
+		match value {
+			Some(v) => {
+				merged.insert(name.as_str(), v);
+			}
+			None => conflicts.push(name.trim_end_matches(':').to_string()),
+		}
+	}
+
+	if !conflicts.is_empty() {
+		return Err(conflicts);
+	}
+
+	// Start from the side whose task text is kept, so its words stay as
+	// they were written
+	let mut todo = match merged.get("task") == Some(&Some(theirs.task())) {
+		true => theirs.clone(),
+		false => ours.clone(),
+	};
+	let date = |name: &str| {
+		merged
+			.get(name)
+			.cloned()
+			.flatten()
+			.and_then(|d| chrono::NaiveDate::parse_from_str(&d, "%Y-%m-%d").ok())
+	};
+
+	todo.is_complete = merged.get("completion").cloned().flatten().is_some();
+	todo.completed_at = date("completion date");
+	todo.created_at = date("creation date");
+	todo.priority = merged
+		.get("priority")
+		.cloned()
+		.flatten()
+		.and_then(|p| p.chars().next());
+
+	for (name, value) in &merged {
+		let key = match name.strip_suffix(':') {
+			Some(key) => key,
+			None => continue,
+		};
+		let values: Vec<&str> = value.iter().flat_map(|v| v.split(' ')).collect();
+		let current: Vec<String> = todo
+			.key_values()
+			.filter(|(k, _)| *k == key)
+			.map(|(_, v)| v.to_string())
+			.collect();
+
+		// A single value is changed in place, repeated ones are written
+		// again at the end
+		match (values.as_slice(), current.len()) {
+			_ if current == values => {}
+			([], _) => todo.remove_key_value(key),
+			([v], 0 | 1) => todo.set_key_value(key, v),
+			_ => {
+				todo.remove_key_value(key);
+
+				for v in values {
+					todo.push_text(&format!("{}:{}", key, v));
+				}
+			}
+		}
+	}
+
+	Ok(todo)
+}
+
+/// Merge one line, None if it is removed
+fn merge_line(base: Option<&str>, ours: Option<&str>, theirs: Option<&str>) -> Option<Merged> {
+	if let Some(line) = merge_value(base, ours, theirs) {
+		return line.map(|l| Merged::Line(l.to_string()));
+	}
+
+	let conflict = |fields: Vec<String>| {
+		Some(Merged::Conflict {
+			ours: ours.map(String::from),
+			theirs: theirs.map(String::from),
+			fields,
+		})
+	};
+
+	let (ours_line, theirs_line) = match (ours, theirs) {
+		(Some(o), Some(t)) => (o, t),
+		(None, _) => return conflict(vec![String::from("removed by ours")]),
+		(_, None) => return conflict(vec![String::from("removed by theirs")]),
+	};
+
+	match (ours_line.parse::<Todo>(), theirs_line.parse::<Todo>()) {
+		(Ok(o), Ok(t)) => {
+			let b = base.and_then(|b| b.parse::<Todo>().ok());
+
+			match merge_todos(b.as_ref(), &o, &t) {
+				Ok(todo) => Some(Merged::Line(todo.serialize())),
+				Err(fields) => conflict(fields),
+			}
+		}
+		_ => conflict(vec![String::from("line")]),
+	}
+}
+
+/// Merge `ours` and `theirs`, both changed from `base`. Lines are kept in
+/// the order of `ours`. A line only `theirs` has goes after the line it
+/// follows in `theirs`, and after the lines `ours` added there.
+pub fn merge(base: &str, ours: &str, theirs: &str) -> Vec<Merged> {
+	let base_lines: HashMap<Key, &str> = keyed_lines(base).into_iter().collect();
+	let our_lines = keyed_lines(ours);
+	let their_lines = keyed_lines(theirs);
+	let ours_by_key: HashMap<&Key, &str> = our_lines.iter().map(|(k, l)| (k, *l)).collect();
+	let theirs_by_key: HashMap<&Key, &str> = their_lines.iter().map(|(k, l)| (k, *l)).collect();
+
+	// Lines only theirs has, by the last line before them both sides have
+	let mut following: HashMap<Option<&Key>, Vec<&Key>> = HashMap::new();
+	let mut previous = None;
+
+	for (key, _) in &their_lines {
+		match ours_by_key.contains_key(key) {
+			true => previous = Some(key),
+			false => following.entry(previous).or_default().push(key),
+		}
+	}
+
+	let mut keys = Vec::new();
+	let mut pending = following.remove(&None).unwrap_or_default();
+
+	for (key, _) in &our_lines {
+		if theirs_by_key.contains_key(key) {
+			keys.append(&mut pending);
+			pending.extend(following.remove(&Some(key)).unwrap_or_default());
+		}
+
+		keys.push(key);
+	}
+
+	keys.append(&mut pending);
+
+	let mut seen = HashSet::new();
+	let mut result = Vec::new();
+
+	for key in keys {
+		if !seen.insert(key) {
+			continue;
+		}
+
+		result.extend(merge_line(
+			base_lines.get(key).copied(),
+			ours_by_key.get(key).copied(),
+			theirs_by_key.get(key).copied(),
+		));
+	}
+
+	result
+}
+
+/// Number of conflicts in `merged`
+pub fn count_conflicts(merged: &[Merged]) -> usize {
+	merged
+		.iter()
+		.filter(|m| matches!(m, Merged::Conflict { .. }))
+		.count()
+}
+
+/// Text of a merged file, with conflicts between the markers git uses
+pub fn to_text(merged: &[Merged]) -> String {
+	let mut text = String::new();
+
+	for m in merged {
+		match m {
+			Merged::Line(l) => {
+				text.push_str(l);
+				text.push('\n');
+			}
+			Merged::Conflict {
+				ours,
+				theirs,
+				fields,
+			} => {
+				text.push_str(&format!("<<<<<<< ours ({})\n", fields.join(", ")));
+
+				if let Some(l) = ours {
+					text.push_str(&format!("{}\n", l));
+				}
+
+				text.push_str("=======\n");
+
+				if let Some(l) = theirs {
+					text.push_str(&format!("{}\n", l));
+				}
+
+				text.push_str(">>>>>>> theirs\n");
+			}
+		}
+	}
+
+	text
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const ID: &str = "id:2a6a3d2e-4c0a-4c6e-9d3b-7a1f3b8d7c11";
+
+	fn line(text: &str) -> String {
+		format!("{} {}", text, ID)
+	}
+
+	fn merge_one(base: &str, ours: &str, theirs: &str) -> Vec<Merged> {
+		merge(&line(base), &line(ours), &line(theirs))
+	}
+
+	#[test]
+	fn merge_fields_changed_on_either_side() {
+		assert_eq!(
+			merge_one("call mom", "(A) call mom", "x call mom"),
+			vec![Merged::Line(format!("x call mom {} pri:A", ID))]
+		);
+		assert_eq!(
+			merge_one("call mom", "call mom due:2021-12-25", "call dad"),
+			vec![Merged::Line(format!("call dad {} due:2021-12-25", ID))]
+		);
+	}
+
+	#[test]
+	fn merge_adds_up_clocked_time() {
+		assert_eq!(
+			merge_one(
+				"call mom clocked:1h",
+				"call mom clocked:1h30m",
+				"call mom clocked:1h20m"
+			),
+			vec![Merged::Line(line("call mom clocked:1h50m"))]
+		);
+	}
+
+	#[test]
+	fn merge_conflicts() {
+		assert_eq!(
+			merge_one("call mom", "(A) call mom", "(B) call mom"),
+			vec![Merged::Conflict {
+				ours: Some(line("(A) call mom")),
+				theirs: Some(line("(B) call mom")),
+				fields: vec![String::from("priority")],
+			}]
+		);
+		assert_eq!(
+			merge(&line("call mom"), "", &line("x call mom")),
+			vec![Merged::Conflict {
+				ours: None,
+				theirs: Some(line("x call mom")),
+				fields: vec![String::from("removed by ours")],
+			}]
+		);
+	}
+
+	#[test]
+	fn merge_repeated_keys() {
+		assert_eq!(
+			merge_one(
+				"call mom at:home at:work",
+				"(A) call mom at:home at:work",
+				"call mom at:home at:phone"
+			),
+			vec![Merged::Line(line("(A) call mom at:home at:phone"))]
+		);
+	}
+
+	#[test]
+	fn merge_keeps_lines_inserted_by_theirs_in_place() {
+		let base = "buy milk\ncall mom\nwater plants\n";
+		let ours = "buy milk\ncall mom\nwrite report\nwater plants\nbook flight\n";
+		let theirs = "buy milk\nbuy bread\ncall mom\nwater plants\n";
+		let merged = merge(base, ours, theirs);
+
+		assert_eq!(
+			to_text(&merged),
+			"buy milk\nbuy bread\ncall mom\nwrite report\nwater plants\nbook flight\n"
+		);
+	}
+
+	#[test]
+	fn merge_additions_and_removals() {
+		let base = "buy milk\ncall mom\n";
+		let ours = "buy milk\nwrite report\n";
+		let theirs = "call mom\nbuy milk\nbuy bread\n";
+		let merged = merge(base, ours, theirs);
+
+		assert_eq!(count_conflicts(&merged), 0);
+		assert_eq!(to_text(&merged), "buy milk\nwrite report\nbuy bread\n");
+	}
+}
@@ -0,0 +1,101 @@
+/// Errors found while parsing or checking todo.txt lines
+use std::error::Error;
+use std::fmt;
+use uuid::Uuid;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// What is wrong with a line
+pub enum ParseErrorKind {
+	/// The line has no task text
+	NoTask,
+
+	/// A date is not a valid YYYY-MM-DD date
+	InvalidDate(String),
+
+	/// `pri:` is not a single letter A-Z
+	InvalidPriority(String),
+
+	/// `id:` is not a UUID
+	InvalidId(String),
+
+	/// `id:` is already used by the todo on the given line
+	DuplicateId(Uuid, usize),
+
+	/// The short id is already the short id of the todo on the given line
+	ShortIdCollision(String, usize),
+
+	/// `clock:` is not a unix timestamp
+	InvalidClock(String),
+
+	/// `clocked:` is not an XhXmXs duration
+	InvalidClocked(String),
+
+	/// `rep:` is not a known repeat pattern
+	InvalidRepeat(String),
+
+	/// The line is not valid UTF-8
+	InvalidUtf8,
+}
+
+impl fmt::Display for ParseErrorKind {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ParseErrorKind::NoTask => write!(f, "could not find title of todo"),
+			ParseErrorKind::InvalidDate(v) => write!(f, "invalid date '{}'", v),
+			ParseErrorKind::InvalidPriority(v) => write!(f, "invalid priority '{}'", v),
+			ParseErrorKind::InvalidId(v) => write!(f, "invalid id '{}'", v),
+			ParseErrorKind::DuplicateId(id, line) => {
+				write!(f, "duplicate id '{}', first used on line {}", id, line)
+			}
+			ParseErrorKind::ShortIdCollision(id, line) => write!(
+				f,
+				"short id '{}' is also the short id of line {}, select these todos by number",
+				id, line
+			),
+			ParseErrorKind::InvalidClock(v) => write!(f, "invalid clock timestamp '{}'", v),
+			ParseErrorKind::InvalidClocked(v) => write!(f, "invalid clocked duration '{}'", v),
+			ParseErrorKind::InvalidRepeat(v) => write!(f, "invalid repeat pattern '{}'", v),
+			ParseErrorKind::InvalidUtf8 => write!(f, "invalid UTF-8, replaced with U+FFFD"),
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A problem with a todo.txt line, and where it was found
+pub struct ParseError {
+	/// Line number in the file, starting at 1. 0 if unknown.
+	pub line: usize,
+
+	/// Character column in the line, starting at 1
+	pub column: usize,
+
+	pub kind: ParseErrorKind,
+}
+
+impl ParseError {
+	pub fn new(column: usize, kind: ParseErrorKind) -> ParseError {
+		ParseError {
+			line: 0,
+			column,
+			kind,
+		}
+	}
+
+	/// Set the line number the error was found on
+	pub fn at_line(mut self, line: usize) -> ParseError {
+		self.line = line;
+		self
+	}
+}
+
+impl fmt::Display for ParseError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if self.line > 0 {
+			write!(f, "{}:{}: {}", self.line, self.column, self.kind)
+		} else {
+			write!(f, "column {}: {}", self.column, self.kind)
+		}
+	}
+}
+
+impl Error for ParseError {}
@@ -0,0 +1,657 @@
+/// Boolean queries over todos, as given to `ls`
+///
+/// A query is made of search terms combined with `and`, `or`, `not` and
+/// parentheses. Terms next to each other must all match, and a leading
+/// `-` negates a term. A term is one of
+///
+/// - text the todo line contains, such as `mom` or `+Fam`, or a quoted
+///   `"call mom"` phrase. A leading `\` makes any word text, `\/usr`,
+///   `\-x` or `\or` search for `/usr`, `-x` and `or`.
+/// - a regular expression between slashes, `/^x .*mom/`, `/Mom/i` to
+///   ignore case
+/// - `project:Family`, `context:phone` or `tag:urgent`, the todo having
+///   exactly that project, context or hashtag
+/// - a comparison of a field: `pri`, the dates `due`, `t`, `created` and
+///   `completed`, or any key of a `key:value` attribute
+///
+/// Priorities compare by importance, so `pri>=B` matches A and B. Dates
+/// are YYYY-MM-DD, `today`, `tomorrow` or `yesterday`. Other keys can
+/// only be compared with `=` and `!=`. A todo without the field matches
+/// no comparison but `!=`.
+use chrono::{Duration, Local, NaiveDate};
+use regex::{Regex, RegexBuilder};
+use std::cell::OnceCell;
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+
+use crate::todo::Todo;
+use crate::token::is_key;
+
+/// Why a query could not be parsed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum QueryError {
+	/// A `(` without its `)` or the other way around
+	UnbalancedParenthesis,
+
+	/// A quoted phrase or regular expression missing its closing
+	/// character
+	Unterminated(char),
+
+	/// `and`, `or` or `not` without a term to apply to, with what was
+	/// found instead
+	MissingTerm(String),
+
+	/// A regular expression that does not compile, and why
+	InvalidRegex(String, String),
+
+	/// A value a field can not be compared with
+	InvalidValue(String, String),
+
+	/// A field compared with an operator other than `=` or `!=` that has
+	/// no order
+	Unordered(String, String),
+}
+
+impl fmt::Display for QueryError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			QueryError::UnbalancedParenthesis => write!(f, "unbalanced parenthesis in query"),
+			QueryError::Unterminated(c) => write!(f, "missing closing '{}' in query", c),
+			QueryError::MissingTerm(found) => {
+				write!(f, "expected a search term in query, found {}", found)
+			}
+			QueryError::InvalidRegex(pattern, e) => {
+				write!(f, "invalid regular expression '{}': {}", pattern, e)
+			}
+			QueryError::InvalidValue(field, value) => {
+				write!(f, "invalid value '{}' for {}", value, field)
+			}
+			QueryError::Unordered(field, op) => write!(
+				f,
+				"{} can not be compared with '{}', only with = or !=",
+				field, op
+			),
+		}
+	}
+}
+
+impl Error for QueryError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Comparison {
+	Lt,
+	Le,
+	Eq,
+	Ne,
+	Ge,
+	Gt,
+}
+
+impl Comparison {
+	fn parse(op: &str) -> Comparison {
+		match op {
+			"<" => Comparison::Lt,
+			"<=" => Comparison::Le,
+			"!=" => Comparison::Ne,
+			">=" => Comparison::Ge,
+			">" => Comparison::Gt,
+			_ => Comparison::Eq,
+		}
+	}
+
+	/// true if a field ordered `order` against the value compared with
+	/// satisfies the comparison. A missing field only satisfies `!=`.
+	fn holds(self, order: Option<Ordering>) -> bool {
+		match (self, order) {
+			(Comparison::Ne, None) => true,
+			(_, None) => false,
+			(Comparison::Lt, Some(o)) => o == Ordering::Less,
+			(Comparison::Le, Some(o)) => o != Ordering::Greater,
+			(Comparison::Eq, Some(o)) => o == Ordering::Equal,
+			(Comparison::Ne, Some(o)) => o != Ordering::Equal,
+			(Comparison::Ge, Some(o)) => o != Ordering::Less,
+			(Comparison::Gt, Some(o)) => o == Ordering::Greater,
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DateField {
+	Due,
+	Threshold,
+	Created,
+	Completed,
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+	/// The empty query, matching every todo
+	All,
+
+	And(Box<Expr>, Box<Expr>),
+	Or(Box<Expr>, Box<Expr>),
+	Not(Box<Expr>),
+
+	/// Text the serialized todo contains
+	Text(String),
+
+	/// Expression the serialized todo matches
+	Regex(Regex),
+
+	Project(String),
+	Context(String),
+	Tag(String),
+	Priority(Comparison, char),
+	Date(DateField, Comparison, NaiveDate),
+
+	/// Value of a key value attribute
+	KeyValue(String, Comparison, String),
+}
+
+impl Expr {
+	/// Add to `words` the projects, contexts and tags every todo matching
+	/// the expression has, as they are written in its line
+	fn required_words(&self, words: &mut Vec<String>) {
+		match self {
+			Expr::And(a, b) => {
+				a.required_words(words);
+				b.required_words(words);
+			}
+			Expr::Project(w) | Expr::Context(w) | Expr::Tag(w) => words.push(w.clone()),
+			Expr::Text(w) if w.starts_with(['+', '@', '#']) && !w.contains(char::is_whitespace) => {
+				words.push(w.clone())
+			}
+			_ => {}
+		}
+	}
+}
+
+/// Word of a query
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+	Open,
+	Close,
+	And,
+	Or,
+	Not,
+
+	/// A term as written, to be read by `term`
+	Word(String),
+
+	/// A quoted phrase, always searched for as text
+	Phrase(String),
+
+	/// A regular expression, and whether it ignores case
+	Regex(String, bool),
+}
+
+/// Read `end` terminated text from `chars`, after its opening character.
+/// `\` followed by `end` stands for `end`.
+fn read_until(chars: &mut std::iter::Peekable<std::str::Chars>, end: char) -> Option<String> {
+	let mut text = String::new();
+
+	while let Some(c) = chars.next() {
+		match c {
+			'\\' if chars.peek() == Some(&end) => text.push(chars.next()?),
+			c if c == end => return Some(text),
+			c => text.push(c),
+		}
+	}
+
+	None
+}
+
+/// Split `arg` into tokens
+fn lex(arg: &str, tokens: &mut Vec<Token>) -> Result<(), QueryError> {
+	let mut chars = arg.chars().peekable();
+
+	while let Some(&c) = chars.peek() {
+		match c {
+			c if c.is_whitespace() => {
+				chars.next();
+			}
+			'(' => {
+				chars.next();
+				tokens.push(Token::Open);
+			}
+			')' => {
+				chars.next();
+				tokens.push(Token::Close);
+			}
+			'"' => {
+				chars.next();
+
+				let phrase = read_until(&mut chars, '"').ok_or(QueryError::Unterminated('"'))?;
+
+				tokens.push(Token::Phrase(phrase));
+			}
+			'/' => {
+				chars.next();
+
+				let pattern = read_until(&mut chars, '/').ok_or(QueryError::Unterminated('/'))?;
+				let ignore_case = chars.next_if_eq(&'i').is_some();
+
+				tokens.push(Token::Regex(pattern, ignore_case));
+			}
+			'-' => {
+				chars.next();
+
+				match chars.peek() {
+					Some(c) if !c.is_whitespace() => tokens.push(Token::Not),
+					_ => tokens.push(Token::Word(String::from("-"))),
+				}
+			}
+			_ => {
+				let escaped = chars.next_if_eq(&'\\').is_some();
+				let mut word = String::new();
+
+				if let Some(c) = chars.next_if(|c| escaped && !c.is_whitespace()) {
+					word.push(c);
+				}
+
+				while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != ')') {
+					word.push(c);
+				}
+
+				tokens.push(match word.as_str() {
+					"" if escaped => Token::Phrase(String::from("\\")),
+					_ if escaped => Token::Phrase(word),
+					"and" | "AND" => Token::And,
+					"or" | "OR" => Token::Or,
+					"not" | "NOT" => Token::Not,
+					_ => Token::Word(word),
+				});
+			}
+		}
+	}
+
+	Ok(())
+}
+
+/// Date written as `value`, relative to `today`
+fn date(value: &str, today: NaiveDate) -> Option<NaiveDate> {
+	match value {
+		"today" => Some(today),
+		"tomorrow" => Some(today + Duration::days(1)),
+		"yesterday" => Some(today - Duration::days(1)),
+		_ => NaiveDate::parse_from_str(value, "%Y-%m-%d").ok(),
+	}
+}
+
+/// Comparison of `field` with `value`
+fn comparison(field: &str, op: &str, value: &str) -> Result<Expr, QueryError> {
+	let invalid = || QueryError::InvalidValue(field.to_string(), value.to_string());
+	let comparison = Comparison::parse(op);
+	let date_field = match field {
+		"due" => Some(DateField::Due),
+		"t" => Some(DateField::Threshold),
+		"created" => Some(DateField::Created),
+		"completed" => Some(DateField::Completed),
+		_ => None,
+	};
+
+	if let Some(date_field) = date_field {
+		let date = date(value, Local::today().naive_local()).ok_or_else(invalid)?;
+
+		return Ok(Expr::Date(date_field, comparison, date));
+	}
+
+	if field == "pri" {
+		let mut chars = value.chars();
+
+		return match (chars.next(), chars.next()) {
+			(Some(p), None) if p.is_ascii_alphabetic() => {
+				Ok(Expr::Priority(comparison, p.to_ascii_uppercase()))
+			}
+			_ => Err(invalid()),
+		};
+	}
+
+	if !matches!(comparison, Comparison::Eq | Comparison::Ne) {
+		return Err(QueryError::Unordered(field.to_string(), op.to_string()));
+	}
+
+	if value.is_empty() {
+		return Err(invalid());
+	}
+
+	Ok(Expr::KeyValue(
+		field.to_string(),
+		comparison,
+		value.to_string(),
+	))
+}
+
+/// `word` split into the field, operator and value of a comparison,
+/// None unless the part before the operator is a key
+fn split_comparison(word: &str) -> Option<(&str, &str, &str)> {
+	let (field, rest) = word.split_at(word.find(['<', '>', '=', '!'])?);
+	let op = ["<=", ">=", "!=", "=", "<", ">"]
+		.into_iter()
+		.find(|op| rest.starts_with(op))?;
+
+	is_key(field).then(|| (field, op, &rest[op.len()..]))
+}
+
+/// Term written as `word`
+fn term(word: &str) -> Result<Expr, QueryError> {
+	if let Some((field, name)) = word.split_once(':') {
+		let name = name.trim_start_matches(['+', '@', '#']);
+
+		if !name.is_empty() {
+			match field {
+				"project" => return Ok(Expr::Project(format!("+{}", name))),
+				"context" => return Ok(Expr::Context(format!("@{}", name))),
+				"tag" => return Ok(Expr::Tag(format!("#{}", name))),
+				_ => {}
+			}
+		}
+	}
+
+	match split_comparison(word) {
+		Some((field, op, value)) => comparison(field, op, value),
+		None => Ok(Expr::Text(word.to_string())),
+	}
+}
+
+/// Recursive descent parser of a query, from the loosest binding
+/// operator, `or`, to the tightest, `not`
+struct Parser {
+	tokens: Vec<Token>,
+	position: usize,
+}
+
+impl Parser {
+	fn peek(&self) -> Option<&Token> {
+		self.tokens.get(self.position)
+	}
+
+	fn next(&mut self) -> Option<Token> {
+		let token = self.tokens.get(self.position).cloned();
+
+		self.position += 1;
+		token
+	}
+
+	fn or(&mut self) -> Result<Expr, QueryError> {
+		let mut expr = self.and()?;
+
+		while self.peek() == Some(&Token::Or) {
+			self.next();
+			expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+		}
+
+		Ok(expr)
+	}
+
+	fn and(&mut self) -> Result<Expr, QueryError> {
+		let mut expr = self.not()?;
+
+		loop {
+			match self.peek() {
+				Some(Token::And) => {
+					self.next();
+				}
+				None | Some(Token::Or) | Some(Token::Close) => return Ok(expr),
+				_ => {}
+			}
+
+			expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+		}
+	}
+
+	fn not(&mut self) -> Result<Expr, QueryError> {
+		if self.peek() == Some(&Token::Not) {
+			self.next();
+
+			return Ok(Expr::Not(Box::new(self.not()?)));
+		}
+
+		self.primary()
+	}
+
+	fn primary(&mut self) -> Result<Expr, QueryError> {
+		match self.next() {
+			Some(Token::Open) => {
+				let expr = self.or()?;
+
+				match self.next() {
+					Some(Token::Close) => Ok(expr),
+					_ => Err(QueryError::UnbalancedParenthesis),
+				}
+			}
+			Some(Token::Word(w)) => term(&w),
+			Some(Token::Phrase(p)) => Ok(Expr::Text(p)),
+			Some(Token::Regex(pattern, ignore_case)) => RegexBuilder::new(&pattern)
+				.case_insensitive(ignore_case)
+				.build()
+				.map(Expr::Regex)
+				.map_err(|e| QueryError::InvalidRegex(pattern, e.to_string())),
+			Some(Token::Close) => Err(QueryError::UnbalancedParenthesis),
+			Some(Token::And) => Err(QueryError::MissingTerm(String::from("'and'"))),
+			Some(Token::Or) => Err(QueryError::MissingTerm(String::from("'or'"))),
+			Some(Token::Not) | None => Err(QueryError::MissingTerm(String::from(
+				"the end of the query",
+			))),
+		}
+	}
+}
+
+/// A todo and its serialized line, worked out once it is needed
+struct Subject<'a> {
+	todo: &'a Todo,
+	line: OnceCell<String>,
+}
+
+impl Subject<'_> {
+	fn line(&self) -> &str {
+		self.line.get_or_init(|| self.todo.serialize())
+	}
+
+	fn date(&self, field: DateField) -> Option<NaiveDate> {
+		match field {
+			DateField::Due => self.todo.due().ok().flatten(),
+			DateField::Threshold => self.todo.threshold().ok().flatten(),
+			DateField::Created => self.todo.created_at,
+			DateField::Completed => self.todo.completed_at,
+		}
+	}
+
+	fn matches(&self, expr: &Expr) -> bool {
+		match expr {
+			Expr::All => true,
+			Expr::And(a, b) => self.matches(a) && self.matches(b),
+			Expr::Or(a, b) => self.matches(a) || self.matches(b),
+			Expr::Not(e) => !self.matches(e),
+			Expr::Text(text) => self.line().contains(text.as_str()),
+			Expr::Regex(re) => re.is_match(self.line()),
+			Expr::Project(p) => self.todo.projects().contains(&p.as_str()),
+			Expr::Context(c) => self.todo.contexts().contains(&c.as_str()),
+			Expr::Tag(t) => self.todo.tags().contains(&t.as_str()),
+			// A is the most important, so it compares as the greatest
+			Expr::Priority(op, p) => op.holds(self.todo.priority.map(|own| p.cmp(&own))),
+			Expr::Date(field, op, date) => op.holds(self.date(*field).map(|own| own.cmp(date))),
+			Expr::KeyValue(key, op, value) => op.holds(
+				self.todo
+					.key_value(key)
+					.map(|own| own.cmp(value.as_str()))
+					.map(|o| match o {
+						Ordering::Equal => o,
+						_ => Ordering::Less,
+					}),
+			),
+		}
+	}
+}
+
+/// A parsed query, see the module documentation
+#[derive(Clone, Debug)]
+pub struct Query {
+	expr: Expr,
+}
+
+impl Query {
+	/// Parse the query made of `args`, as given on the command line. The
+	/// terms of separate arguments must all match.
+	pub fn parse<S: AsRef<str>>(args: &[S]) -> Result<Query, QueryError> {
+		let mut tokens = Vec::new();
+
+		for arg in args {
+			lex(arg.as_ref(), &mut tokens)?;
+		}
+
+		if tokens.is_empty() {
+			return Ok(Query { expr: Expr::All });
+		}
+
+		let mut parser = Parser {
+			tokens,
+			position: 0,
+		};
+		let expr = parser.or()?;
+
+		match parser.peek() {
+			None => Ok(Query { expr }),
+			Some(_) => Err(QueryError::UnbalancedParenthesis),
+		}
+	}
+
+	/// true for the empty query, which matches every todo
+	pub fn is_empty(&self) -> bool {
+		matches!(self.expr, Expr::All)
+	}
+
+	/// Words the line of every matching todo contains: the `+project`,
+	/// `@context` and `#tag` terms that must all match. A file can be
+	/// read for the query without parsing lines missing one of them, see
+	/// `todo_file::parse_todos_containing`.
+	pub fn required_words(&self) -> Vec<String> {
+		let mut words = Vec::new();
+
+		self.expr.required_words(&mut words);
+		words
+	}
+
+	pub fn matches(&self, todo: &Todo) -> bool {
+		Subject {
+			todo,
+			line: OnceCell::new(),
+		}
+		.matches(&self.expr)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Which of `lines` match `query`, by their position
+	fn matching(query: &str, lines: &[&str]) -> Vec<usize> {
+		let query = Query::parse(&[query]).unwrap();
+
+		lines
+			.iter()
+			.enumerate()
+			.filter(|(_, l)| query.matches(&l.parse::<Todo>().unwrap()))
+			.map(|(i, _)| i)
+			.collect()
+	}
+
+	const LINES: &[&str] = &[
+		"(A) call mom +Family @phone due:2021-12-25",
+		"(C) 2021-01-10 write report +Work #urgent due:2021-12-01 client:acme",
+		"x 2021-12-02 2021-01-05 buy milk @store",
+		"plan trip +Family-Vacation t:2999-01-01",
+	];
+
+	#[test]
+	fn text_and_boolean_operators() {
+		assert_eq!(matching("", LINES), vec![0, 1, 2, 3]);
+		assert_eq!(matching("+Family", LINES), vec![0, 3]);
+		assert_eq!(matching("+Family -@phone", LINES), vec![3]);
+		assert_eq!(matching("-+Family-Vacation", LINES), vec![0, 1, 2]);
+		assert_eq!(matching("mom or milk", LINES), vec![0, 2]);
+		assert_eq!(matching("(mom or milk) and not @store", LINES), vec![0]);
+		assert_eq!(matching("not (mom or milk)", LINES), vec![1, 3]);
+		assert_eq!(matching("\"call mom\" or \"buy  milk\"", LINES), vec![0]);
+		assert_eq!(matching("/^x .*milk/ or /REPORT/i", LINES), vec![1, 2]);
+	}
+
+	#[test]
+	fn escaped_words_are_text() {
+		let lines = &["fix /usr/local path", "or -v flag", "read (draft) pri>=A"];
+
+		assert_eq!(matching(r"\/usr", lines), vec![0]);
+		assert_eq!(matching(r"\-v \or", lines), vec![1]);
+		assert_eq!(matching(r"\pri>=A", lines), vec![2]);
+		assert_eq!(matching(r"\(draft", lines), vec![2]);
+	}
+
+	#[test]
+	fn words_without_a_key_are_text() {
+		let lines = &["check 1+1=2", "fix a.b=c", "<3 it", "=> arrow"];
+
+		assert_eq!(matching("1+1=2", lines), vec![0]);
+		assert_eq!(matching("a.b=c", lines), vec![1]);
+		assert_eq!(matching("<3", lines), vec![2]);
+		assert_eq!(matching("=>", lines), vec![3]);
+	}
+
+	#[test]
+	fn field_predicates() {
+		assert_eq!(matching("project:Family", LINES), vec![0]);
+		assert!(matching("context:@store tag:urgent", LINES).is_empty());
+		assert_eq!(matching("context:store or tag:#urgent", LINES), vec![1, 2]);
+		assert_eq!(matching("pri>=C", LINES), vec![0, 1]);
+		assert_eq!(matching("pri<b", LINES), vec![1]);
+		assert_eq!(matching("pri!=A", LINES), vec![1, 2, 3]);
+		assert_eq!(matching("due<2021-12-10", LINES), vec![1]);
+		assert_eq!(
+			matching("due>=2021-12-01 due<=2021-12-25", LINES),
+			vec![0, 1]
+		);
+		assert_eq!(matching("created>2021-01-05", LINES), vec![1]);
+		assert_eq!(matching("completed<=today", LINES), vec![2]);
+		assert_eq!(matching("t>tomorrow", LINES), vec![3]);
+		assert_eq!(matching("client=acme", LINES), vec![1]);
+		assert_eq!(matching("client!=acme", LINES), vec![0, 2, 3]);
+	}
+
+	#[test]
+	fn query_errors() {
+		let error = |query: &str| Query::parse(&[query]).unwrap_err().to_string();
+
+		assert_eq!(error("(mom or milk"), "unbalanced parenthesis in query");
+		assert_eq!(error("mom)"), "unbalanced parenthesis in query");
+		assert_eq!(error("\"call mom"), "missing closing '\"' in query");
+		assert_eq!(
+			error("mom or"),
+			"expected a search term in query, found the end of the query"
+		);
+		assert_eq!(
+			error("and mom"),
+			"expected a search term in query, found 'and'"
+		);
+		assert_eq!(error("due<soon"), "invalid value 'soon' for due");
+		assert_eq!(error("pri>=AB"), "invalid value 'AB' for pri");
+		assert_eq!(
+			error("client<acme"),
+			"client can not be compared with '<', only with = or !="
+		);
+		assert!(error("/(/").starts_with("invalid regular expression '('"));
+	}
+
+	#[test]
+	fn required_words() {
+		let words = |query: &str| Query::parse(&[query]).unwrap().required_words();
+
+		assert_eq!(
+			words("+Garden mom project:Car -@home tag:#urgent"),
+			vec!["+Garden", "+Car", "#urgent"]
+		);
+		assert!(words("+Garden or +Car").is_empty());
+		assert!(words("not (+Garden @home)").is_empty());
+		assert!(words(r#""+Garden work""#).is_empty());
+	}
+}
@@ -1,4 +1,5 @@
 use std::ops::Add;
+use std::str::FromStr;
 
 use chrono::{Datelike, Duration, Local, NaiveDate, Weekday};
 use regex::Regex;
@@ -8,57 +9,91 @@ lazy_static! {
 		Regex::new(r"^(?P<frequency>\d+)(?P<unit>[dwmy])$").unwrap();
 }
 
-/// Step through
-fn compute_month(ref_d: NaiveDate, months: u32) -> NaiveDate {
-	let mut month = ref_d.month();
-	let mut year = ref_d.year();
+/// Unit of a repeat pattern
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RepeatUnit {
+	Day,
+	Week,
+	Month,
+	Year,
+}
+
+/// A parsed repeat pattern, such as `2w`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RepeatRule {
+	pub frequency: u32,
+	pub unit: RepeatUnit,
+}
+
+/// Step `months` months forward from `ref_d`, keeping the day of the
+/// month where possible and using the last day of shorter months.
+fn compute_month(ref_d: NaiveDate, months: u32) -> Option<NaiveDate> {
+	let month0 = ref_d.month0() as i64 + months as i64;
+	let year = i32::try_from(ref_d.year() as i64 + month0 / 12).ok()?;
+	let month = (month0 % 12) as u32 + 1;
 
-	for _ in 0..months {
-		month += 1;
-		if month > 12 {
-			year += 1;
-			month = 1;
+	(1..=ref_d.day())
+		.rev()
+		.find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+}
+
+impl RepeatRule {
+	/// Compute the next date based on reference date (or now). None if
+	/// the date would be out of range.
+	pub fn next_date(&self, reference_date: Option<NaiveDate>) -> Option<NaiveDate> {
+		let ref_d: NaiveDate = match reference_date {
+			None => Local::today().naive_local(),
+			Some(v) => v,
+		};
+
+		match self.unit {
+			RepeatUnit::Day => ref_d.checked_add_signed(Duration::days(self.frequency as i64)),
+			RepeatUnit::Week => ref_d.checked_add_signed(Duration::weeks(self.frequency as i64)),
+			RepeatUnit::Month => compute_month(ref_d, self.frequency),
+			RepeatUnit::Year => compute_month(ref_d, self.frequency.checked_mul(12)?),
 		}
 	}
+}
 
-	NaiveDate::from_ymd(year, month, ref_d.day())
+impl FromStr for RepeatRule {
+	type Err = String;
+
+	/// Parse a repeat pattern.
+	///
+	/// Patterns supported:
+	///
+	///   1d = every day
+	///   7d = every 7 days
+	///   1w = every week
+	///   1m = every month
+	///   1y = every year
+	fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+		let invalid = || format!("unknown repeat pattern '{}'", pattern);
+		let matches = REPEAT_PATTERN_RE.captures(pattern).ok_or_else(invalid)?;
+		let frequency = matches["frequency"].parse::<u32>().map_err(|_| invalid())?;
+		let unit = match &matches["unit"] {
+			"d" => RepeatUnit::Day,
+			"w" => RepeatUnit::Week,
+			"m" => RepeatUnit::Month,
+			_ => RepeatUnit::Year,
+		};
+
+		Ok(RepeatRule { frequency, unit })
+	}
 }
 
 /// Compute the next date based on reference date (or now)
-/// and the repeat pattern.
-///
-/// Patterns supported:
-///
-///   1d = every day
-///   7d = every 7 days
-///   1w = every week
-///   1m = every month
-///   1y = every year
+/// and the repeat pattern. See `RepeatRule` for the patterns supported.
 pub fn next_date(pattern: &str, reference_date: Option<NaiveDate>) -> Option<NaiveDate> {
-	let ref_d: NaiveDate = match reference_date {
-		None => Local::today().naive_local(),
-		Some(v) => v,
-	};
+	pattern
+		.parse::<RepeatRule>()
+		.ok()?
+		.next_date(reference_date)
+}
 
-	match REPEAT_PATTERN_RE.captures(pattern) {
-		None => None,
-		Some(matches) => {
-			let frequency = matches.name("frequency")?.as_str().parse::<u32>().unwrap();
-			let unit = matches.name("unit")?.as_str();
-
-			match unit {
-				"d" => Some(ref_d.add(Duration::days(frequency as i64))),
-				"w" => Some(ref_d.add(Duration::weeks(frequency as i64))),
-				"m" => Some(compute_month(ref_d, frequency)),
-				"y" => Some(NaiveDate::from_ymd(
-					ref_d.year() + frequency as i32,
-					ref_d.month(),
-					ref_d.day(),
-				)),
-				_ => None,
-			}
-		}
-	}
+/// true if `pattern` is a repeat pattern `RepeatRule` understands
+pub fn is_valid(pattern: &str) -> bool {
+	pattern.parse::<RepeatRule>().is_ok()
 }
 
 pub fn next_weekday(weekday: Weekday, reference_date: Option<NaiveDate>) -> NaiveDate {
@@ -161,6 +196,42 @@ mod tests {
 		assert_eq!(n.day(), 1);
 	}
 
+	#[test]
+	fn test_1_month_from_end_of_month() {
+		let n = next_date("1m", Some(NaiveDate::from_ymd(2021, 1, 31))).unwrap();
+
+		assert_eq!(n, NaiveDate::from_ymd(2021, 2, 28));
+	}
+
+	#[test]
+	fn test_1_year_from_leap_day() {
+		let n = next_date("1y", Some(NaiveDate::from_ymd(2020, 2, 29))).unwrap();
+
+		assert_eq!(n, NaiveDate::from_ymd(2021, 2, 28));
+	}
+
+	#[test]
+	fn test_out_of_range() {
+		let d = Some(NaiveDate::from_ymd(2021, 1, 1));
+
+		assert_eq!(next_date("4000000000d", d), None);
+		assert_eq!(next_date("4000000000y", d), None);
+		assert_eq!(next_date("99999999999999999999d", d), None);
+	}
+
+	#[test]
+	fn test_parse_rule() {
+		assert_eq!(
+			"2w".parse::<RepeatRule>(),
+			Ok(RepeatRule {
+				frequency: 2,
+				unit: RepeatUnit::Week
+			})
+		);
+		assert!("2x".parse::<RepeatRule>().is_err());
+		assert!("w".parse::<RepeatRule>().is_err());
+	}
+
 	#[test]
 	fn test_next_weekday_fri_to_sat() {
 		let n = next_weekday(Weekday::Sat, Some(NaiveDate::from_ymd(2021, 1, 1)));
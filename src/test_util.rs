@@ -0,0 +1,25 @@
+/// Fixtures shared by the tests of several modules
+use std::fs;
+use std::path::PathBuf;
+
+/// Path for `name` in the temporary directory, unique to this test run
+fn scratch_path(name: &str) -> PathBuf {
+	std::env::temp_dir().join(format!("todo-txt-{}-{}", std::process::id(), name))
+}
+
+/// Path of a scratch file for `name`, removed if left by an earlier run
+pub fn scratch_file(name: &str) -> String {
+	let path = scratch_path(name);
+	let _ = fs::remove_file(&path);
+
+	path.to_str().unwrap().to_string()
+}
+
+/// Empty scratch directory for `name`
+pub fn scratch_dir(name: &str) -> PathBuf {
+	let dir = scratch_path(name);
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+
+	dir
+}
@@ -1,17 +1,110 @@
-use chrono::{Local, NaiveDate, TimeZone};
-use regex::Regex;
+use chrono::{DateTime, Duration, Local, NaiveDate, TimeZone};
+use regex::{Captures, Regex};
+use std::borrow::Cow;
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::fmt::Write;
 use std::str::FromStr;
 use uuid::Uuid;
 
+use crate::cfg::{self, IdStrategy};
 use crate::hms;
+use crate::parse_error::{ParseError, ParseErrorKind};
+use crate::repeat_time::RepeatRule;
+use crate::token::{self, Token, TokenKind};
 
 lazy_static! {
 	static ref PARSE_RE:      Regex = Regex::new(r"^(?P<complete>x )?(?:\((?P<priority>[A-Z])\))?\s*(?P<date1>\d{4}-\d{2}-\d{2})?\s*(?P<date2>\d{4}-\d{2}-\d{2})?\s*(?P<task>.+$)").unwrap();
-	static ref PROJECTS_RE:   Regex = Regex::new(r"\+([^\s]+)").unwrap();
-	static ref CONTEXTS_RE:   Regex = Regex::new(r"@([^\s]+)").unwrap();
-	static ref KEY_VALUES_RE: Regex = Regex::new(r"(?P<key>[^\s]+):(?P<value>[^\s]+)").unwrap();
+	static ref PREFIX_RE:     Regex = Regex::new(r"^(?P<complete>x )?(?:\((?P<priority>[A-Z])\))?\s*(?P<date1>\d{4}-\d{2}-\d{2})?\s*(?P<date2>\d{4}-\d{2}-\d{2})?\s*").unwrap();
+}
+
+/// Match `line` against `PARSE_RE`, returning the captures and where the
+/// task starts
+///
+/// `PREFIX_RE` is tried first. It only has to read up to the task, which
+/// is much faster on long lines, and gives the same captures whenever
+/// some task text is left after it.
+fn parse_captures(line: &str) -> Option<(Captures<'_>, usize)> {
+	if let Some(m) = PREFIX_RE.captures(line) {
+		let end = m.get(0).map_or(0, |p| p.end());
+		let task = &line[end..];
+
+		if !task.is_empty() && !task.contains('\n') {
+			return Some((m, end));
+		}
+	}
+
+	let m = PARSE_RE.captures(line)?;
+	let start = m.name("task")?.start();
+
+	Some((m, start))
+}
+
+/// Character column (starting at 1) of byte `offset` in `line`
+fn column(line: &str, offset: usize) -> usize {
+	line[..offset].chars().count() + 1
+}
+
+/// `date`, matched as `\d{4}-\d{2}-\d{2}`, read without the format
+/// parser of `NaiveDate::parse_from_str`, which is slow on large files
+fn ymd(date: &str) -> Option<NaiveDate> {
+	let year = date.get(0..4)?.parse().ok()?;
+	let month = date.get(5..7)?.parse().ok()?;
+	let day = date.get(8..10)?.parse().ok()?;
+
+	NaiveDate::from_ymd_opt(year, month, day)
+}
+
+fn parse_date(line: &str, m: Option<regex::Match>) -> Result<Option<NaiveDate>, ParseError> {
+	match m {
+		None => Ok(None),
+		Some(t) => match ymd(t.as_str()) {
+			None => Err(ParseError::new(
+				column(line, t.start()),
+				ParseErrorKind::InvalidDate(t.as_str().to_string()),
+			)),
+			Some(d) => Ok(Some(d)),
+		},
+	}
+}
+
+/// `name` as a hashtag, with exactly one leading `#`
+fn tag_word(name: &str) -> String {
+	format!("#{}", name.trim_start_matches('#'))
+}
+
+/// true if `value` is a valid priority, A-Z
+fn is_priority(value: &str) -> bool {
+	value.len() == 1 && value.chars().all(|c| c.is_ascii_uppercase())
+}
+
+/// Parse an id written as a full UUID or as the 8 character short form.
+/// Short ids keep their characters as the start of the UUID.
+fn parse_id(value: &str) -> Option<Uuid> {
+	if let Ok(id) = Uuid::parse_str(value) {
+		return Some(id);
+	}
+
+	if value.len() != 8 || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+		return None;
+	}
+
+	let start = u32::from_str_radix(value, 16).ok()?;
+
+	Uuid::from_fields(start, 0, 0, &[0; 8]).ok()
+}
+
+/// Id derived from the task text (128 bit FNV-1a, see `Todo::id_text`)
+/// and the number of todos with the same text before it, for todos that
+/// do not store an id, so that reading the file again gives the same id
+fn hash_id(task: &str, occurrence: u32) -> Uuid {
+	let mut hash: u128 = 0x6c62272e07bb014262b821756295c58d;
+
+	for byte in task.bytes().chain(occurrence.to_le_bytes()) {
+		hash ^= byte as u128;
+		hash = hash.wrapping_mul(0x0000000001000000000000000000013b);
+	}
+
+	Uuid::from_u128(hash)
 }
 
 fn serialize(
@@ -21,25 +114,25 @@ fn serialize(
 	task: &str,
 	priority: Option<char>,
 ) -> String {
-	let mut out = Vec::new();
+	let mut out = String::with_capacity(task.len() + 26);
 
 	if is_complete {
-		out.push(String::from("x"));
+		out.push_str("x ");
 	} else if let Some(p) = priority {
-		out.push(format!("({})", p));
+		let _ = write!(out, "({}) ", p);
 	}
 
 	if let Some(d) = completed_at {
-		out.push(d.format("%Y-%m-%d").to_string());
+		let _ = write!(out, "{} ", d.format("%Y-%m-%d"));
 	}
 
 	if let Some(d) = created_at {
-		out.push(d.format("%Y-%m-%d").to_string());
+		let _ = write!(out, "{} ", d.format("%Y-%m-%d"));
 	}
 
-	out.push(String::from(task));
+	out.push_str(task);
 
-	out.join(" ")
+	out
 }
 
 #[derive(Clone, Debug)]
@@ -62,141 +155,532 @@ pub struct Todo {
 	/// Date the task was completed
 	pub completed_at: Option<NaiveDate>,
 
-	/// Task title
-	pub task: String,
-
 	/// Priority (if any), A-Z.
 	pub priority: Option<char>,
 
-	/// Project tags (+Project)
-	pub projects: Vec<String>,
+	/// Task text, word by word, as it was written
+	tokens: Vec<Token>,
 
-	/// Context tags (@context)
-	pub contexts: Vec<String>,
+	/// Column of the line the task text started at
+	column: usize,
 
-	/// Key value attributes (key:value)
-	pub key_values: HashMap<String, String>,
+	/// How `id` is read from and written to the line
+	identity: IdStrategy,
 }
 
 impl Todo {
-	pub fn serialize(&self) -> String {
-		let mut serialize_kv_pairs = self.key_values.clone();
+	/// Parse `line`, reading and writing its id as `identity` says.
+	/// `str::parse` uses the configured `cfg::get_id_strategy`.
+	pub fn parse_with_identity(line: &str, identity: IdStrategy) -> Result<Todo, ParseError> {
+		let (m, task_start) = match parse_captures(line) {
+			Some(matches) => matches,
+			None => return Err(ParseError::new(1, ParseErrorKind::NoTask)),
+		};
 
-		if self.is_complete {
-			if let Some(t) = self.priority {
-				serialize_kv_pairs.insert("pri".to_string(), format!("{}", t));
-			}
+		let date1 = parse_date(line, m.name("date1"))?;
+		let date2 = parse_date(line, m.name("date2"))?;
+
+		let is_complete = m.name("complete").is_some();
+		let priority = m
+			.name("priority")
+			.map(|p| p.as_str().chars().next().unwrap());
+		let created_at = match date2 {
+			None => date1,
+			Some(_) => date2,
+		};
+		let completed_at = match date2 {
+			None => None,
+			Some(_) => date1,
+		};
+
+		let mut todo = Todo {
+			index: 0,
+			id: Uuid::nil(),
+			created_at,
+			completed_at,
+			is_complete,
+			priority,
+			tokens: token::tokenize(&line[task_start..]),
+			column: column(line, task_start),
+			identity,
+		};
+
+		if todo.priority.is_none() {
+			todo.priority = todo
+				.tokens
+				.iter()
+				.find_map(|t| match &t.kind {
+					TokenKind::KeyValue(k, v) if k == "pri" => Some(v),
+					_ => None,
+				})
+				.filter(|v| is_priority(v))
+				.and_then(|v| v.chars().next());
+		}
+
+		todo.id = todo.stored_id().unwrap_or_default();
+		todo.set_occurrence(0);
+
+		Ok(todo)
+	}
+
+	/// true if `line` parses as a todo, worked out without reading its
+	/// task, which is much faster than parsing it
+	pub fn is_todo(line: &str) -> bool {
+		parse_captures(line).is_some_and(|(m, _)| {
+			parse_date(line, m.name("date1")).is_ok() && parse_date(line, m.name("date2")).is_ok()
+		})
+	}
+
+	/// Tell a todo that does not store an id apart from the `occurrence`
+	/// todos with the same `id_text` before it in its file
+	pub fn set_occurrence(&mut self, occurrence: u32) {
+		if let Some(text) = self.id_text() {
+			self.id = hash_id(&text, occurrence);
 		}
+	}
 
-		serialize_kv_pairs.insert("id".to_string(), self.id.to_string());
+	/// Id written on the line, if any
+	fn stored_id(&self) -> Option<Uuid> {
+		let key = self.identity.key()?;
 
-		let kv_pairs: Vec<std::string::String> = serialize_kv_pairs
+		self.tokens
 			.iter()
-			.map(|(k, v)| format!("{}:{}", k, v))
-			.collect();
-		let kv_pairs_str = kv_pairs.join(" ");
-
-		let result = format!(
-			"{} {}",
-			serialize(
-				self.is_complete,
-				self.created_at,
-				self.completed_at,
-				&self.task,
-				self.priority
+			.find_map(|t| match &t.kind {
+				TokenKind::KeyValue(k, v) if k == key => Some(v),
+				_ => None,
+			})
+			.and_then(|v| parse_id(v))
+	}
+
+	/// Text the id of a todo that does not store one is derived from,
+	/// None if the line stores its id
+	///
+	/// Without stored ids (`IdStrategy::None`), projects, contexts,
+	/// hashtags and key value attributes are left out, so that tagging a
+	/// todo or moving it to another project keeps its id. Otherwise the
+	/// derived id is written with the next change to the file, and the
+	/// whole task is used: a line that `todo_file::parse_todos_containing`
+	/// skips then never has the same text as a line it reads.
+	pub fn id_text(&self) -> Option<String> {
+		if self.stored_id().is_some() {
+			return None;
+		}
+
+		match self.identity {
+			IdStrategy::None => Some(
+				self.tokens
+					.iter()
+					.filter_map(|t| match &t.kind {
+						TokenKind::Word(w) => Some(w.as_str()),
+						_ => None,
+					})
+					.collect::<Vec<&str>>()
+					.join(" "),
 			),
-			kv_pairs_str
+			_ => Some(token::join(&self.tokens)),
+		}
+	}
+
+	/// true for keys kept in dedicated fields instead of key value
+	/// attributes, the id key and `pri`
+	fn is_reserved_key(&self, key: &str) -> bool {
+		key == "pri" || self.identity.key() == Some(key)
+	}
+
+	/// Serialize the task text. The id and `pri:` are written back where
+	/// they were found, or appended when the line did not have them.
+	fn serialize_text(&self) -> String {
+		let priority = if self.is_complete {
+			self.priority
+		} else {
+			None
+		};
+		let id_key = self.identity.key();
+		let id_value = match self.identity {
+			IdStrategy::Short(_) => self.short_id(),
+			_ => self.id.to_string(),
+		};
+		let mut id_written = false;
+		let mut priority_written = false;
+		let mut out = String::new();
+
+		for t in &self.tokens {
+			match &t.kind {
+				TokenKind::KeyValue(k, _) if Some(k.as_str()) == id_key && !id_written => {
+					id_written = true;
+					token::push(
+						&mut out,
+						&t.space,
+						&TokenKind::KeyValue(k.clone(), id_value.clone()),
+					);
+				}
+				TokenKind::KeyValue(k, v) if k == "pri" && is_priority(v) && !priority_written => {
+					priority_written = true;
+
+					if let Some(p) = priority {
+						token::push(
+							&mut out,
+							&t.space,
+							&TokenKind::KeyValue(k.clone(), p.to_string()),
+						);
+					}
+				}
+				kind => token::push(&mut out, &t.space, kind),
+			}
+		}
+
+		if let (Some(p), false) = (priority, priority_written) {
+			token::push(
+				&mut out,
+				" ",
+				&TokenKind::KeyValue("pri".to_string(), p.to_string()),
+			);
+		}
+
+		if let (Some(key), false) = (id_key, id_written) {
+			token::push(
+				&mut out,
+				" ",
+				&TokenKind::KeyValue(key.to_string(), id_value),
+			);
+		}
+
+		out
+	}
+
+	pub fn serialize(&self) -> String {
+		let result = serialize(
+			self.is_complete,
+			self.created_at,
+			self.completed_at,
+			&self.serialize_text(),
+			self.priority,
 		);
-		let result = result.trim().to_string();
+
+		result.trim().to_string()
+	}
+
+	/// All key value attributes, reserved keys included, along with the
+	/// column they were found at
+	fn key_value_columns(&self) -> Vec<(usize, &str, &str)> {
+		let mut result = Vec::new();
+		let mut column = self.column;
+
+		for t in &self.tokens {
+			column += t.space.chars().count();
+
+			if let TokenKind::KeyValue(k, v) = &t.kind {
+				result.push((column, k.as_str(), v.as_str()));
+			}
+
+			column += t.kind.to_string().chars().count();
+		}
 
 		result
 	}
 
-	pub fn is_past_due(&self) -> bool {
-		let due_date = match self.key_values.get("due") {
-			None => return false,
+	/// Column the first `key` attribute was found at
+	pub fn key_column(&self, key: &str) -> Option<usize> {
+		self.key_value_columns()
+			.into_iter()
+			.find(|(_, k, _)| *k == key)
+			.map(|(c, _, _)| c)
+	}
+
+	/// Parse the first `key` attribute with `parse`. An error of `kind`
+	/// pointing at the attribute is returned if it can not be parsed.
+	fn typed_value<T, F>(
+		&self,
+		key: &str,
+		parse: F,
+		kind: fn(String) -> ParseErrorKind,
+	) -> Result<Option<T>, ParseError>
+	where
+		F: FnOnce(&str) -> Option<T>,
+	{
+		let value = match self.tokens.iter().find_map(|t| match &t.kind {
+			TokenKind::KeyValue(k, v) if k == key => Some(v.as_str()),
+			_ => None,
+		}) {
+			None => return Ok(None),
 			Some(v) => v,
 		};
-		let now = Local::now().format("%Y-%m-%d").to_string();
 
-		due_date <= &now
+		// The column is only worked out for the error, it is slow to find
+		match parse(value) {
+			None => Err(ParseError::new(
+				self.key_column(key).unwrap_or(self.column),
+				kind(value.to_string()),
+			)),
+			Some(v) => Ok(Some(v)),
+		}
 	}
 
-	pub fn has_repeat(&self) -> bool {
-		self.key_values.contains_key("rep")
+	/// Due date (due:YYYY-MM-DD)
+	pub fn due(&self) -> Result<Option<NaiveDate>, ParseError> {
+		self.typed_value(
+			"due",
+			|v| NaiveDate::parse_from_str(v, "%Y-%m-%d").ok(),
+			ParseErrorKind::InvalidDate,
+		)
 	}
 
-	pub fn has_clock(&self) -> bool {
-		self.key_values.contains_key("clock")
+	/// Threshold date (t:YYYY-MM-DD). The task is not relevant before it.
+	pub fn threshold(&self) -> Result<Option<NaiveDate>, ParseError> {
+		self.typed_value(
+			"t",
+			|v| NaiveDate::parse_from_str(v, "%Y-%m-%d").ok(),
+			ParseErrorKind::InvalidDate,
+		)
 	}
 
-	fn add_to_clocked(&mut self, amount: i64) {
-		let already_clocked = match self.key_values.get("clocked") {
-			None => 0,
-			Some(v) => hms::to_seconds(v),
-		};
-		let new_clocked = already_clocked + amount;
+	/// Repeat pattern (rep:1w)
+	pub fn repeat(&self) -> Result<Option<RepeatRule>, ParseError> {
+		self.typed_value("rep", |v| v.parse().ok(), ParseErrorKind::InvalidRepeat)
+	}
+
+	/// Time the task was clocked into (clock:<unix timestamp>)
+	pub fn clock_started(&self) -> Result<Option<DateTime<Local>>, ParseError> {
+		self.typed_value(
+			"clock",
+			|v| {
+				let seconds = v.parse::<i64>().ok()?;
+				Local.timestamp_opt(seconds, 0).single()
+			},
+			ParseErrorKind::InvalidClock,
+		)
+	}
+
+	/// Time already clocked on the task (clocked:XhXmXs), not counting a
+	/// clock that is still running
+	pub fn clocked(&self) -> Result<Duration, ParseError> {
+		let clocked = self.typed_value(
+			"clocked",
+			|v| hms::parse(v).map(Duration::seconds),
+			ParseErrorKind::InvalidClocked,
+		)?;
 
-		self.key_values
-			.insert("clocked".to_string(), hms::from_seconds(new_clocked));
+		Ok(clocked.unwrap_or_else(Duration::zero))
 	}
 
-	pub fn clock_in(&mut self) {
-		if self.has_clock() {
-			return;
+	/// Column the id was found at, if the line has one
+	pub fn id_column(&self) -> Option<usize> {
+		self.identity.key().and_then(|key| self.key_column(key))
+	}
+
+	/// Check the values of well known key value attributes: `due`, `t`,
+	/// the id, `pri`, `clock`, `clocked` and `rep`.
+	pub fn validate(&self) -> Vec<ParseError> {
+		let mut errors: Vec<ParseError> = vec![
+			self.due().err(),
+			self.threshold().err(),
+			self.repeat().err(),
+			self.clock_started().err(),
+			self.clocked().err(),
+		]
+		.into_iter()
+		.flatten()
+		.collect();
+
+		let id_key = self.identity.key();
+
+		for (column, key, value) in self.key_value_columns() {
+			let v = value.to_string();
+			let kind = match key {
+				k if Some(k) == id_key && parse_id(value).is_none() => ParseErrorKind::InvalidId(v),
+				"pri" if !is_priority(value) => ParseErrorKind::InvalidPriority(v),
+				_ => continue,
+			};
+
+			errors.push(ParseError::new(column, kind));
 		}
 
-		let now = Local::now();
-		self.key_values
-			.insert("clock".to_string(), format!("{}", now.timestamp()));
+		errors.sort_by_key(|e| e.column);
+		errors
 	}
 
-	pub fn clock_out(&mut self) {
-		if self.has_clock() == false {
-			return;
+	/// Short form of `id`, enough to select the todo in most lists
+	pub fn short_id(&self) -> String {
+		self.id.to_string()[..8].to_string()
+	}
+
+	/// Task title, without key value attributes
+	pub fn task(&self) -> String {
+		token::join(
+			self.tokens
+				.iter()
+				.filter(|t| !matches!(t.kind, TokenKind::KeyValue(_, _))),
+		)
+	}
+
+	/// Project tags (+Project)
+	pub fn projects(&self) -> Vec<&str> {
+		self.tokens
+			.iter()
+			.filter_map(|t| match &t.kind {
+				TokenKind::Project(p) => Some(p.as_str()),
+				_ => None,
+			})
+			.collect()
+	}
+
+	/// Context tags (@context)
+	pub fn contexts(&self) -> Vec<&str> {
+		self.tokens
+			.iter()
+			.filter_map(|t| match &t.kind {
+				TokenKind::Context(c) => Some(c.as_str()),
+				_ => None,
+			})
+			.collect()
+	}
+
+	/// Hashtags (#tag)
+	pub fn tags(&self) -> Vec<&str> {
+		self.tokens
+			.iter()
+			.filter_map(|t| match &t.kind {
+				TokenKind::Tag(c) => Some(c.as_str()),
+				_ => None,
+			})
+			.collect()
+	}
+
+	/// Key value attributes (key:value) in the order they were written
+	pub fn key_values(&self) -> impl Iterator<Item = (&str, &str)> {
+		self.tokens.iter().filter_map(|t| match &t.kind {
+			TokenKind::KeyValue(k, v) if !self.is_reserved_key(k) => Some((k.as_str(), v.as_str())),
+			_ => None,
+		})
+	}
+
+	/// Value of the first `key` attribute
+	pub fn key_value(&self, key: &str) -> Option<&str> {
+		self.key_values().find(|(k, _)| *k == key).map(|(_, v)| v)
+	}
+
+	pub fn has_key_value(&self, key: &str) -> bool {
+		self.key_value(key).is_some()
+	}
+
+	/// Set the `key` attribute to `value`, in place if the task already
+	/// has one, otherwise at the end of the task.
+	pub fn set_key_value(&mut self, key: &str, value: &str) {
+		let existing = self.tokens.iter_mut().find_map(|t| match &mut t.kind {
+			TokenKind::KeyValue(k, v) if k == key => Some(v),
+			_ => None,
+		});
+
+		match existing {
+			Some(v) => *v = value.to_string(),
+			None => self.tokens.push(Token::new(TokenKind::KeyValue(
+				key.to_string(),
+				value.to_string(),
+			))),
+		}
+	}
+
+	/// Remove every `key` attribute
+	pub fn remove_key_value(&mut self, key: &str) {
+		self.tokens
+			.retain(|t| !matches!(&t.kind, TokenKind::KeyValue(k, _) if k == key));
+	}
+
+	/// Append `text` to the end of the task
+	pub fn push_text(&mut self, text: &str) {
+		let mut tokens = token::tokenize(text);
+
+		if let Some(first) = tokens.first_mut() {
+			first.space = Cow::Borrowed(" ");
 		}
 
-		let now = Local::now();
-		let current_clock = self
-			.key_values
-			.get("clock")
-			.unwrap()
-			.parse::<i64>()
-			.unwrap();
-		let elapsed = now.timestamp() - current_clock;
+		self.tokens.append(&mut tokens);
+	}
+
+	/// true if the task is tagged with `name` (with or without `#`)
+	pub fn has_tag(&self, name: &str) -> bool {
+		let tag = tag_word(name);
 
-		self.add_to_clocked(elapsed);
-		self.key_values.remove("clock");
+		self.tags().contains(&tag.as_str())
 	}
 
-	pub fn elapsed_time(&self) -> String {
-		let clocked_time = match self.key_values.get("clocked") {
-			None => 0,
-			Some(t) => hms::to_seconds(t),
-		};
+	/// Tag the task with `name` (with or without `#`) unless it already is
+	pub fn add_tag(&mut self, name: &str) {
+		if !self.has_tag(name) {
+			self.tokens.push(Token::new(TokenKind::Tag(tag_word(name))));
+		}
+	}
 
-		let in_progress_time = match self.key_values.get("clock") {
-			None => 0,
-			Some(clock) => {
-				let now = Local::now();
+	/// Remove every `name` tag (with or without `#`) from the task
+	pub fn remove_tag(&mut self, name: &str) {
+		let tag = tag_word(name);
 
-				let seconds = match clock.parse::<i64>() {
-					Err(_) => 0,
-					Ok(v) => v,
-				};
+		self.tokens
+			.retain(|t| !matches!(&t.kind, TokenKind::Tag(w) if *w == tag));
+	}
 
-				let todo_clock_in = Local.timestamp(seconds, 0);
+	pub fn is_past_due(&self) -> bool {
+		match self.due() {
+			Ok(Some(due_date)) => due_date <= Local::today().naive_local(),
+			_ => false,
+		}
+	}
 
-				(now - todo_clock_in).num_seconds()
-			}
+	/// true unless the task has a threshold date after today. Invalid
+	/// threshold dates count as reached.
+	pub fn is_threshold_reached(&self) -> bool {
+		match self.threshold() {
+			Ok(Some(threshold)) => threshold <= Local::today().naive_local(),
+			_ => true,
+		}
+	}
+
+	pub fn has_repeat(&self) -> bool {
+		self.has_key_value("rep")
+	}
+
+	pub fn has_clock(&self) -> bool {
+		self.has_key_value("clock")
+	}
+
+	pub fn clock_in(&mut self) {
+		if self.has_clock() {
+			return;
+		}
+
+		let now = Local::now();
+		self.set_key_value("clock", &format!("{}", now.timestamp()));
+	}
+
+	/// Stop the running clock, adding its time to `clocked:`. Nothing is
+	/// changed if either value is invalid.
+	pub fn clock_out(&mut self) -> Result<(), ParseError> {
+		let started = match self.clock_started()? {
+			None => return Ok(()),
+			Some(v) => v,
 		};
+		let clocked = self.clocked()? + (Local::now() - started);
 
-		hms::from_seconds(in_progress_time + clocked_time)
+		self.set_key_value("clocked", &hms::from_seconds(clocked.num_seconds()));
+		self.remove_key_value("clock");
+
+		Ok(())
 	}
 
+	pub fn elapsed_time(&self) -> String {
+		hms::from_seconds(self.elapsed_time_as_seconds())
+	}
+
+	/// Clocked time plus the time of a running clock. Invalid values are
+	/// counted as no time.
 	pub fn elapsed_time_as_seconds(&self) -> i64 {
-		let et = self.elapsed_time();
-		hms::to_seconds(&et)
+		let clocked_time = self.clocked().map(|d| d.num_seconds()).unwrap_or(0);
+		let in_progress_time = match self.clock_started() {
+			Ok(Some(started)) => (Local::now() - started).num_seconds(),
+			_ => 0,
+		};
+
+		clocked_time + in_progress_time
 	}
 
 	pub fn reset(&mut self, dates: bool) {
@@ -207,34 +691,32 @@ impl Todo {
 		self.is_complete = false;
 		self.completed_at = None;
 
-		self.key_values.remove("clock");
-		self.key_values.remove("clocked");
+		self.remove_key_value("clock");
+		self.remove_key_value("clocked");
 	}
 
 	/// Compare two Todo structures by priority and task title
+	#[allow(clippy::should_implement_trait)]
 	pub fn cmp(&self, b: &Todo) -> Ordering {
 		if self.is_complete == b.is_complete {
-			if self.priority.is_none() && b.priority.is_none() {
-				self.task.cmp(&b.task)
-			} else if self.priority.is_none() {
-				Ordering::Greater
-			} else if b.priority.is_none() {
-				Ordering::Less
-			} else {
-				let apri = self.priority.unwrap();
-				let bpri = b.priority.unwrap();
-				let priority_result = apri.cmp(&bpri);
-
-				if priority_result == Ordering::Equal {
-					if self.is_complete == b.is_complete {
-						self.task.cmp(&b.task)
-					} else if self.is_complete {
-						Ordering::Greater
+			match (self.priority, b.priority) {
+				(None, None) => self.cmp_by_title(b),
+				(None, Some(_)) => Ordering::Greater,
+				(Some(_), None) => Ordering::Less,
+				(Some(apri), Some(bpri)) => {
+					let priority_result = apri.cmp(&bpri);
+
+					if priority_result == Ordering::Equal {
+						if self.is_complete == b.is_complete {
+							self.cmp_by_title(b)
+						} else if self.is_complete {
+							Ordering::Greater
+						} else {
+							Ordering::Less
+						}
 					} else {
-						Ordering::Less
+						priority_result
 					}
-				} else {
-					priority_result
 				}
 			}
 		} else if self.is_complete {
@@ -246,12 +728,12 @@ impl Todo {
 
 	/// Compare two Todo structures by title alone
 	pub fn cmp_by_title(&self, b: &Todo) -> Ordering {
-		self.task.cmp(&b.task)
+		self.task().cmp(&b.task())
 	}
 
 	pub fn cmp_by_due_date(&self, b: &Todo) -> Ordering {
-		let duea = self.key_values.get("due");
-		let dueb = b.key_values.get("due");
+		let duea = self.due().ok().flatten();
+		let dueb = b.due().ok().flatten();
 
 		if self.is_complete && !b.is_complete {
 			Ordering::Greater
@@ -270,105 +752,11 @@ impl Todo {
 }
 
 impl FromStr for Todo {
-	type Err = String;
+	type Err = ParseError;
 
 	/// Create a new Todo structure from the given raw line.
 	fn from_str(line: &str) -> Result<Self, Self::Err> {
-		let m = match PARSE_RE.captures(line) {
-			Some(matches) => matches,
-			None => return Err(String::from("Could not find basic todo information")),
-		};
-
-		let task = match m.name("task") {
-			None => return Err(String::from("Could not find title of todo")),
-			Some(t) => String::from(t.as_str()),
-		};
-
-		let date1 = match m.name("date1") {
-			None => None,
-			Some(t) => match NaiveDate::parse_from_str(t.as_str(), "%Y-%m-%d") {
-				Err(e) => {
-					return Err(String::from(format!(
-						"error parsing date1: '{}', {}",
-						t.as_str(),
-						e
-					)))
-				}
-				Ok(t) => Some(t),
-			},
-		};
-		let date2 = match m.name("date2") {
-			None => None,
-			Some(t) => match NaiveDate::parse_from_str(t.as_str(), "%Y-%m-%d") {
-				Err(e) => {
-					return Err(String::from(format!(
-						"error parsing date2: '{}', {}",
-						t.as_str(),
-						e
-					)))
-				}
-				Ok(t) => Some(t),
-			},
-		};
-
-		let projects = PROJECTS_RE
-			.captures_iter(&task)
-			.map(|cap| String::from(&cap[0]))
-			.collect();
-		let contexts = CONTEXTS_RE
-			.captures_iter(&task)
-			.map(|cap| String::from(&cap[0]))
-			.collect();
-		let mut key_values: HashMap<String, String> = KEY_VALUES_RE
-			.captures_iter(&task)
-			.map(|cap| (String::from(&cap[1]), String::from(&cap[2])))
-			.collect();
-		let is_complete = m.name("complete").is_some();
-		let mut priority = m
-			.name("priority")
-			.map(|p| p.as_str().chars().next().unwrap());
-		let created_at = match date2 {
-			None => date1,
-			Some(_) => date2,
-		};
-		let completed_at = match date2 {
-			None => None,
-			Some(_) => date1,
-		};
-		let id = if let Some(v) = key_values.get("id") {
-			match Uuid::parse_str(v) {
-				Err(_) => Uuid::new_v4(),
-				Ok(u) => u,
-			}
-		} else {
-			Uuid::new_v4()
-		};
-
-		if priority == None {
-			priority = match key_values.get("pri") {
-				Some(v) => Some(v.chars().next().unwrap()),
-				None => None,
-			}
-		}
-
-		key_values.remove("id");
-		key_values.remove("pri");
-
-		let task = KEY_VALUES_RE.replace_all(&task, "").to_string();
-		let task = task.trim().to_string();
-
-		Ok(Todo {
-			index: 0,
-			id,
-			created_at,
-			completed_at,
-			is_complete,
-			task,
-			priority,
-			projects,
-			contexts,
-			key_values,
-		})
+		Todo::parse_with_identity(line, cfg::get_id_strategy())
 	}
 }
 
@@ -380,11 +768,11 @@ mod tests {
 	fn parse_simple_todo() {
 		let t = "Say hello to mom".parse::<Todo>().unwrap();
 
-		assert_eq!(t.task, "Say hello to mom");
+		assert_eq!(t.task(), "Say hello to mom");
 		assert!(!t.is_complete, "should not be completed");
 		assert!(t.priority.is_none(), "should not have a priority");
-		assert_eq!(t.projects.len(), 0);
-		assert_eq!(t.contexts.len(), 0);
+		assert_eq!(t.projects().len(), 0);
+		assert_eq!(t.contexts().len(), 0);
 	}
 
 	#[test]
@@ -405,16 +793,37 @@ mod tests {
 	fn parse_todo_with_projects() {
 		let t = "Say hello to mom +Family".parse::<Todo>().unwrap();
 
-		assert_eq!(t.projects.len(), 1);
-		assert_eq!(t.projects[0], "+Family");
+		assert_eq!(t.projects().len(), 1);
+		assert_eq!(t.projects()[0], "+Family");
 	}
 
 	#[test]
 	fn parse_todo_with_contexts() {
 		let t = "Say hello to mom @phone".parse::<Todo>().unwrap();
 
-		assert_eq!(t.contexts.len(), 1);
-		assert_eq!(t.contexts[0], "@phone");
+		assert_eq!(t.contexts().len(), 1);
+		assert_eq!(t.contexts()[0], "@phone");
+	}
+
+	#[test]
+	fn parse_todo_with_tags() {
+		let t = "Say hello to mom #family #phone".parse::<Todo>().unwrap();
+
+		assert_eq!(t.tags(), vec!["#family", "#phone"]);
+		assert!(t.has_tag("family"));
+		assert!(t.has_tag("#phone"));
+		assert!(!t.has_tag("fam"));
+	}
+
+	#[test]
+	fn add_and_remove_tags() {
+		let mut t = "hello #a world#b due:2021-01-01".parse::<Todo>().unwrap();
+
+		t.add_tag("a");
+		t.add_tag("b");
+		t.remove_tag("#a");
+
+		serialize_test_todo(&t, "hello world#b due:2021-01-01 #b");
 	}
 
 	#[test]
@@ -423,14 +832,12 @@ mod tests {
 			.parse::<Todo>()
 			.unwrap();
 
-		assert!(t.key_values.contains_key("due"), "should contain a due key");
-		assert_eq!(t.key_values.get("due"), Some(&String::from("2018-12-25")));
+		assert!(t.has_key_value("due"), "should contain a due key");
+		assert_eq!(t.key_value("due"), Some("2018-12-25"));
 
-		assert!(
-			t.key_values.contains_key("time"),
-			"should contain a time key"
-		);
-		assert_eq!(t.key_values.get("time"), Some(&String::from("1am")));
+		assert!(t.has_key_value("time"), "should contain a time key");
+		assert_eq!(t.key_value("time"), Some("1am"));
+		assert_eq!(t.task(), "Say hello to mom");
 	}
 
 	#[test]
@@ -467,14 +874,59 @@ mod tests {
 		assert!(t.is_err(), "result should be an error");
 	}
 
+	#[test]
+	fn is_todo_agrees_with_parse() {
+		for line in [
+			"x (A) 2021-01-01 2021-01-02 task",
+			"(B) call mom",
+			"",
+			"x 2021-01-01 2021-01-02",
+			"(C) 2021-99-99 Hello World",
+			"x 2021-01-01 2021-99-99 H",
+		] {
+			assert_eq!(
+				Todo::is_todo(line),
+				line.parse::<Todo>().is_ok(),
+				"{:?}",
+				line
+			);
+		}
+	}
+
+	#[test]
+	fn parse_prefix_matches_full_expression() {
+		let groups = |m: &Captures| -> Vec<Option<std::ops::Range<usize>>> {
+			(1..5).map(|i| m.get(i).map(|g| g.range())).collect()
+		};
+
+		for line in [
+			"x (A) 2021-01-01 2021-01-02 task",
+			"(A)2021-01-01task",
+			"x ",
+			"(A)",
+			"2021-01-01",
+			"x 2021-01-01 2021-01-02",
+			"2021-01-01 \u{a0}task",
+			"x 2021-01-01 two\nlines",
+		] {
+			let fast = parse_captures(line).map(|(m, start)| (groups(&m), start));
+			let full = PARSE_RE
+				.captures(line)
+				.map(|m| (groups(&m), m.name("task").unwrap().start()));
+
+			assert_eq!(fast, full, "{:?}", line);
+		}
+	}
+
 	#[test]
 	fn parse_todo_with_invalid_create_date() {
 		let t = "(C) 2021-99-99 Hello World".parse::<Todo>();
 
 		assert!(t.is_err(), "result should be an error");
-		assert!(
-			t.unwrap_err().contains("error parsing date1"),
-			"error message should reference date1"
+		assert_eq!(
+			t.unwrap_err(),
+			ParseError::new(5, ParseErrorKind::InvalidDate("2021-99-99".to_string())),
+			"error should reference date1"
 		);
 	}
 
@@ -483,15 +935,130 @@ mod tests {
 		let t = "x 2021-01-01 2021-99-99 H".parse::<Todo>();
 
 		assert!(t.is_err(), "result should be an error");
-		assert!(
-			t.unwrap_err().contains("error parsing date2"),
-			"error message should reference date1"
+		assert_eq!(
+			t.unwrap_err(),
+			ParseError::new(14, ParseErrorKind::InvalidDate("2021-99-99".to_string())),
+			"error should reference date2"
+		);
+	}
+
+	#[test]
+	fn validate_well_known_keys() {
+		let t = "(A) 2021-01-01 héllo due:2021-02-30 clock:x ok:1 clocked:1q rep:2z pri:a id:x"
+			.parse::<Todo>()
+			.unwrap();
+		let errors: Vec<(usize, ParseErrorKind)> = t
+			.validate()
+			.into_iter()
+			.map(|e| (e.column, e.kind))
+			.collect();
+
+		assert_eq!(
+			errors,
+			vec![
+				(22, ParseErrorKind::InvalidDate("2021-02-30".to_string())),
+				(37, ParseErrorKind::InvalidClock("x".to_string())),
+				(50, ParseErrorKind::InvalidClocked("1q".to_string())),
+				(61, ParseErrorKind::InvalidRepeat("2z".to_string())),
+				(68, ParseErrorKind::InvalidPriority("a".to_string())),
+				(74, ParseErrorKind::InvalidId("x".to_string())),
+			]
+		);
+	}
+
+	#[test]
+	fn typed_accessors() {
+		let t = "hello due:2021-02-03 rep:2w clock:1600000000 clocked:1h2m"
+			.parse::<Todo>()
+			.unwrap();
+
+		assert_eq!(t.due(), Ok(Some(NaiveDate::from_ymd(2021, 2, 3))));
+		assert_eq!(t.repeat(), Ok(Some("2w".parse::<RepeatRule>().unwrap())));
+		assert_eq!(t.clock_started().unwrap().unwrap().timestamp(), 1600000000);
+		assert_eq!(t.clocked(), Ok(Duration::seconds(3720)));
+	}
+
+	#[test]
+	fn threshold() {
+		let t = "hello t:2021-02-03".parse::<Todo>().unwrap();
+
+		assert_eq!(t.threshold(), Ok(Some(NaiveDate::from_ymd(2021, 2, 3))));
+		assert!(t.is_threshold_reached());
+
+		let t = "hello t:2999-01-01".parse::<Todo>().unwrap();
+		assert!(!t.is_threshold_reached());
+
+		let t = "hello t:soon".parse::<Todo>().unwrap();
+		assert!(t.threshold().is_err());
+		assert!(t.is_threshold_reached());
+	}
+
+	#[test]
+	fn typed_accessors_missing() {
+		let t = "hello".parse::<Todo>().unwrap();
+
+		assert_eq!(t.due(), Ok(None));
+		assert_eq!(t.repeat(), Ok(None));
+		assert_eq!(t.clock_started(), Ok(None));
+		assert_eq!(t.clocked(), Ok(Duration::zero()));
+	}
+
+	#[test]
+	fn typed_accessors_invalid() {
+		let t = "hello due:tomorrow clock:99999999999999999 clocked:x"
+			.parse::<Todo>()
+			.unwrap();
+
+		assert_eq!(
+			t.due(),
+			Err(ParseError::new(
+				7,
+				ParseErrorKind::InvalidDate("tomorrow".to_string())
+			))
 		);
+		assert!(t.clock_started().is_err());
+		assert!(t.clocked().is_err());
+		assert!(!t.is_past_due());
+	}
+
+	#[test]
+	fn clock_out_with_invalid_clock() {
+		let mut t = "hello clock:abc clocked:1h".parse::<Todo>().unwrap();
+
+		assert!(t.clock_out().is_err());
+		assert_eq!(t.key_value("clock"), Some("abc"));
+		assert_eq!(t.key_value("clocked"), Some("1h"));
+	}
+
+	#[test]
+	fn clock_out_adds_to_clocked() {
+		let clock = Local::now().timestamp() - 60;
+		let mut t = format!("hello clock:{} clocked:1h", clock)
+			.parse::<Todo>()
+			.unwrap();
+
+		t.clock_out().unwrap();
+
+		assert!(!t.has_clock());
+		assert_eq!(t.clocked().unwrap().num_minutes(), 61);
+	}
+
+	#[test]
+	fn validate_valid_todo() {
+		let t = "x 2021-01-02 hello due:2021-02-03 clock:1 clocked:1h2m rep:1w pri:B"
+			.parse::<Todo>()
+			.unwrap();
+
+		assert_eq!(t.validate(), vec![]);
 	}
 
 	fn serialize_test(val: &str) {
 		let t = val.parse::<Todo>().unwrap();
 
+		serialize_test_todo(&t, val);
+	}
+
+	fn serialize_test_todo(t: &Todo, val: &str) {
 		// Need to remove the automatically added id:xyz before
 		// comparing to the original source. Since these are random
 		// ids, it's hard to test against with the content being
@@ -527,4 +1094,139 @@ mod tests {
 	fn serialize_todo_with_create_and_complete_date() {
 		serialize_test("x 2021-01-02 2021-01-01 hello world");
 	}
+
+	#[test]
+	fn serialize_keeps_key_value_order() {
+		serialize_test("hello due:2021-01-01 world rep:1w +Project b:2 a:1");
+	}
+
+	#[test]
+	fn parse_todo_with_url() {
+		let line = "Fix https://example.com/issues/1 due:2021-01-01";
+		let t = line.parse::<Todo>().unwrap();
+
+		assert_eq!(t.task(), "Fix https://example.com/issues/1");
+		assert_eq!(t.key_values().count(), 1);
+		serialize_test(line);
+	}
+
+	#[test]
+	fn serialize_keeps_spacing() {
+		serialize_test("(B) hello  world\tdue:2021-01-01");
+	}
+
+	#[test]
+	fn serialize_keeps_id_in_place() {
+		let line = "hello id:2a6a3d2e-4c0a-4c6e-9d3b-7a1f3b8d7c11 world due:2021-01-01";
+		let t = line.parse::<Todo>().unwrap();
+
+		assert_eq!(t.serialize(), line);
+	}
+
+	#[test]
+	fn serialize_with_custom_id_key() {
+		let identity = IdStrategy::Uuid("uuid".to_string());
+		let line = "hello id:1 uuid:2a6a3d2e-4c0a-4c6e-9d3b-7a1f3b8d7c11";
+		let t = Todo::parse_with_identity(line, identity).unwrap();
+
+		assert_eq!(t.key_value("id"), Some("1"));
+		assert_eq!(t.serialize(), line);
+	}
+
+	#[test]
+	fn serialize_with_short_id() {
+		let identity = IdStrategy::Short("id".to_string());
+		let line = "hello id:2a6a3d2e-4c0a-4c6e-9d3b-7a1f3b8d7c11 world";
+		let t = Todo::parse_with_identity(line, identity.clone()).unwrap();
+
+		assert_eq!(t.serialize(), "hello id:2a6a3d2e world");
+
+		let t = Todo::parse_with_identity("hello id:2a6a3d2e world", identity).unwrap();
+
+		assert_eq!(t.short_id(), "2a6a3d2e");
+		assert!(t.validate().is_empty());
+	}
+
+	#[test]
+	fn serialize_without_id() {
+		let line = "hello id:1 world";
+		let a = Todo::parse_with_identity(line, IdStrategy::None).unwrap();
+		let b = Todo::parse_with_identity(line, IdStrategy::None).unwrap();
+		let c = Todo::parse_with_identity("x (A) hello world id:2", IdStrategy::None).unwrap();
+		let mut d = Todo::parse_with_identity("hello there", IdStrategy::None).unwrap();
+		let e = Todo::parse_with_identity("hello there", IdStrategy::None).unwrap();
+
+		assert_eq!(a.serialize(), line);
+		assert_eq!(a.key_value("id"), Some("1"));
+		assert_eq!(a.id, b.id);
+		assert_eq!(a.id, c.id);
+		assert_ne!(a.id, d.id);
+
+		d.set_occurrence(1);
+		assert_ne!(d.id, e.id);
+	}
+
+	#[test]
+	fn id_derived_when_not_stored() {
+		let identity = IdStrategy::Uuid("id".to_string());
+		let parse = |line: &str| Todo::parse_with_identity(line, identity.clone()).unwrap();
+		let mut a = parse("call mom +Family");
+
+		assert_eq!(a.id, parse("call mom +Family").id);
+		assert_ne!(a.id, parse("call mom +Home").id);
+
+		a.set_occurrence(1);
+		assert_ne!(a.id, parse("call mom +Family").id);
+
+		let line = "call mom id:2a6a3d2e-4c0a-4c6e-9d3b-7a1f3b8d7c11";
+		let mut b = parse(line);
+
+		b.set_occurrence(1);
+		assert_eq!(b.id, parse(line).id);
+		assert_eq!(b.id_text(), None);
+	}
+
+	#[test]
+	fn id_without_stored_id_survives_tagging() {
+		let parse = |line: &str| Todo::parse_with_identity(line, IdStrategy::None).unwrap();
+		let t = parse("call mom +Family @phone");
+		let mut tagged = t.clone();
+
+		tagged.add_tag("urgent");
+		assert_eq!(parse(&tagged.serialize()).id, t.id);
+
+		tagged.remove_tag("urgent");
+		assert_eq!(parse(&tagged.serialize()).id, t.id);
+		assert_eq!(parse("call mom +Home").id, t.id);
+		assert_ne!(parse("call dad +Family").id, t.id);
+	}
+
+	#[test]
+	fn serialize_completed_priority_as_key_value() {
+		let mut t = "(A) hello world".parse::<Todo>().unwrap();
+		t.is_complete = true;
+
+		let t = t.serialize().parse::<Todo>().unwrap();
+
+		assert!(t.is_complete);
+		assert_eq!(t.priority, Some('A'));
+		assert!(t.serialize().starts_with("x hello world pri:A id:"));
+	}
+
+	#[test]
+	fn set_key_value_in_place() {
+		let mut t = "hello due:2021-01-01 world".parse::<Todo>().unwrap();
+		t.set_key_value("due", "2021-02-02");
+		t.set_key_value("rep", "1w");
+
+		serialize_test_todo(&t, "hello due:2021-02-02 world rep:1w");
+	}
+
+	#[test]
+	fn remove_key_value_keeps_other_tokens() {
+		let mut t = "hello clock:1 world clocked:1h".parse::<Todo>().unwrap();
+		t.remove_key_value("clock");
+
+		serialize_test_todo(&t, "hello world clocked:1h");
+	}
 }
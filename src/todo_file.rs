@@ -1,119 +1,843 @@
 /// Read and Write to todo.txt formatted files
-use std::fs::{File, OpenOptions};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
 use std::io;
-use std::io::{prelude::Write, BufRead, BufReader};
-use std::path::PathBuf;
-use std::str::FromStr;
+use std::io::{prelude::Write, BufRead, BufWriter, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::cfg::{get_archive_filename, get_todo_filename};
-use crate::{todo::Todo, todo_list::TodoList};
+use fs2::FileExt;
 
-/// Read all todos from `filename`
+use crate::archive::{compress_old_files, current_file, Archive};
+use crate::backup::backup_default_files;
+use crate::cfg::{
+	get_archive_filename, get_id_strategy, get_lock_timeout, get_todo_filename, IdStrategy,
+};
+use crate::encoding::{self, decode, encode, Decoded, Format};
+use crate::journal::{self, Change, JournalFile};
+use crate::parse_error::{ParseError, ParseErrorKind};
+use crate::todo::Todo;
+use crate::todo_list::{InvalidLine, TodoList};
+
+/// Exclusive advisory lock on a todo file, released when dropped
+///
+/// Commands that read, change and write back todo.txt hold it for the
+/// whole cycle, so two of them running at once can not lose each
+/// other's changes.
+#[derive(Debug)]
+pub struct FileLock {
+	file: File,
+}
+
+impl Drop for FileLock {
+	fn drop(&mut self) {
+		let _ = FileExt::unlock(&self.file);
+	}
+}
+
+/// Lock `filename`, waiting up to `timeout` for another process to
+/// release it
+///
+/// The lock is taken on `<filename>.lock` instead of `filename` itself,
+/// which is replaced by a new file on every write.
+pub fn lock_file(filename: &str, timeout: Duration) -> Result<FileLock, io::Error> {
+	let path = fs::canonicalize(filename).unwrap_or_else(|_| PathBuf::from(filename));
+	let mut lock_path = path.into_os_string();
+	lock_path.push(".lock");
+
+	let file = OpenOptions::new()
+		.write(true)
+		.create(true)
+		.truncate(false)
+		.open(&lock_path)?;
+	let started = Instant::now();
+
+	loop {
+		match file.try_lock_exclusive() {
+			Ok(()) => return Ok(FileLock { file }),
+			Err(e) if e.raw_os_error() != fs2::lock_contended_error().raw_os_error() => {
+				return Err(e)
+			}
+			Err(_) if started.elapsed() >= timeout => {
+				return Err(io::Error::new(
+					io::ErrorKind::TimedOut,
+					format!(
+						"could not lock {}, another todo-txt command is still using it \
+						 (waited {:.1}s)",
+						filename,
+						timeout.as_secs_f64()
+					),
+				))
+			}
+			Err(_) => thread::sleep(Duration::from_millis(50)),
+		}
+	}
+}
+
+/// Lock the user's default todo.txt file. See `lock_file`.
+pub fn lock_default_file() -> Result<FileLock, io::Error> {
+	lock_file(&get_todo_filename(), get_lock_timeout())
+}
+
+/// Lock the user's default todo.txt file to rewrite it. Fails when it
+/// can not be written back (see `encoding::check_rewrite`), before the
+/// command changed anything, such as appending to the archive.
+pub fn lock_default_file_for_rewrite() -> Result<FileLock, io::Error> {
+	let filename = get_todo_filename();
+	let lock = lock_file(&filename, get_lock_timeout())?;
+
+	encoding::check_rewrite(&filename, &encoding::read_file(&filename)?.errors)?;
+
+	Ok(lock)
+}
+
+/// Read all todos from `reader`
+///
+/// Lines that can not be parsed are kept in `TodoList::invalid`. Blank
+/// lines are skipped. See `encoding::decode` for how the bytes read are
+/// turned into lines.
+pub fn read_todos<R: BufRead>(mut reader: R) -> Result<TodoList, io::Error> {
+	let mut bytes = Vec::new();
+
+	reader.read_to_end(&mut bytes)?;
+
+	Ok(read_decoded(decode(&bytes), &[]))
+}
+
+/// Parse the lines of a decoded file
+///
+/// Todos that do not store an id get one derived from their text and
+/// their occurrence of the same text (see `Todo::id_text`), so that
+/// identical lines get different ids and reading the file again gives
+/// the same ones. Unless full UUIDs are written, a short id used by an
+/// earlier todo is replaced by that of a later occurrence if it was
+/// derived and reported in `TodoList::id_errors` if it was read from the
+/// line.
+///
+/// Only lines containing all of `words` are parsed, the others are just
+/// counted to number the todos. As ids of todos can depend on any line
+/// before them unless full UUIDs are written, every line is parsed then.
+fn read_decoded(decoded: Decoded, words: &[String]) -> TodoList {
+	let identity = get_id_strategy();
+	let check_short_ids = !matches!(identity, IdStrategy::Uuid(_));
+	let words = match identity {
+		IdStrategy::Uuid(_) => words,
+		_ => &[],
+	};
+	let mut count = 0;
+	let mut todos = Vec::new();
+	let mut invalid = Vec::new();
+	let mut occurrences: HashMap<String, u32> = HashMap::new();
+	let mut short_ids: HashMap<String, usize> = HashMap::new();
+	let mut id_errors = Vec::new();
+
+	for (i, text) in decoded.text.lines().enumerate() {
+		let line = text.trim_end();
+
+		if line.is_empty() {
+			continue;
+		}
+
+		if !words.iter().all(|w| line.contains(w.as_str())) && Todo::is_todo(line) {
+			count += 1;
+			continue;
+		}
+
+		match Todo::parse_with_identity(line, identity.clone()) {
+			Ok(mut todo) => {
+				todo.index = count;
+				count += 1;
+
+				if let Some(text) = todo.id_text() {
+					let generated = check_short_ids && identity != IdStrategy::None;
+					let occurrence = occurrences.entry(text).or_insert(0);
+
+					todo.set_occurrence(*occurrence);
+					*occurrence += 1;
+
+					while generated && short_ids.contains_key(&todo.short_id()) {
+						todo.set_occurrence(*occurrence);
+						*occurrence += 1;
+					}
+				}
+
+				if check_short_ids {
+					match short_ids.get(&todo.short_id()) {
+						Some(first_line) => id_errors.push(
+							ParseError::new(
+								todo.id_column().unwrap_or(1),
+								ParseErrorKind::ShortIdCollision(todo.short_id(), *first_line),
+							)
+							.at_line(i + 1),
+						),
+						None => {
+							short_ids.insert(todo.short_id(), i + 1);
+						}
+					}
+				}
+
+				todos.push(todo);
+			}
+			Err(error) => invalid.push(InvalidLine {
+				position: count,
+				text: text.to_string(),
+				error: error.at_line(i + 1),
+			}),
+		}
+	}
+
+	TodoList {
+		items: todos,
+		invalid,
+		format: decoded.format,
+		decode_errors: decoded.errors,
+		id_errors,
+	}
+}
+
+/// Check every line read from `reader`. Reports lines that can not be
+/// parsed, invalid values of well known keys and ids used more than once.
+pub fn check_todos<R: BufRead>(mut reader: R) -> Result<Vec<ParseError>, io::Error> {
+	let mut bytes = Vec::new();
+
+	reader.read_to_end(&mut bytes)?;
+
+	let decoded = decode(&bytes);
+	let mut errors = decoded.errors;
+	let mut ids = HashMap::new();
+
+	for (i, line) in decoded.text.lines().enumerate() {
+		let line = line.trim_end();
+		let line_number = i + 1;
+
+		if line.is_empty() {
+			continue;
+		}
+
+		let todo = match line.parse::<Todo>() {
+			Err(e) => {
+				errors.push(e.at_line(line_number));
+				continue;
+			}
+			Ok(t) => t,
+		};
+
+		errors.extend(todo.validate().into_iter().map(|e| e.at_line(line_number)));
+
+		if let Some(column) = todo.id_column() {
+			match ids.get(&todo.id) {
+				Some(first_line) => errors.push(
+					ParseError::new(column, ParseErrorKind::DuplicateId(todo.id, *first_line))
+						.at_line(line_number),
+				),
+				None => {
+					ids.insert(todo.id, line_number);
+				}
+			}
+		}
+	}
+
+	Ok(errors)
+}
+
+/// Read all todos from `filename`. A file that does not exist yet
+/// reads as an empty list.
 pub fn parse_todos(filename: &str) -> Result<TodoList, io::Error> {
-	let filename_path = PathBuf::from_str(filename).unwrap();
+	parse_todos_containing(filename, &[])
+}
 
-	if filename_path.exists() == false {
-		panic!("todo file could not be found: {}", filename_path.display());
+/// Read the todos of `filename` whose line contains all of `words`,
+/// such as the `Query::required_words` of a listing. Todos keep the
+/// numbers they have in the whole file. Skipping the other lines makes
+/// this much faster on large files, but the list must not be written
+/// back: the skipped todos would be lost.
+pub fn parse_todos_containing(filename: &str, words: &[String]) -> Result<TodoList, io::Error> {
+	Ok(read_decoded(encoding::read_file(filename)?, words))
+}
+
+fn warn_invalid(filename: &str, todos: &TodoList) {
+	for e in &todos.decode_errors {
+		eprintln!("warning: {}:{}", filename, e);
+	}
+
+	for l in &todos.invalid {
+		eprintln!("warning: {}:{} (line kept as is)", filename, l.error);
 	}
 
-	let f = File::open(filename)?;
-	let file = BufReader::new(&f);
-	let todos = file
-		.lines()
-		.map(|line| {
-			let lineu = line.unwrap();
-			lineu.parse::<Todo>()
-		})
-		.filter(|t| t.is_ok())
-		.enumerate()
-		.map(|(i, t)| {
-			let mut result = t.unwrap();
-			result.index = i as u32;
-			result
-		})
-		.collect();
+	for e in &todos.id_errors {
+		eprintln!("warning: {}:{}", filename, e);
+	}
+}
+
+/// Read all todos from `filename`, warning on stderr about lines that
+/// could not be parsed.
+pub fn parse_todos_and_warn(filename: &str) -> Result<TodoList, io::Error> {
+	parse_todos_containing_and_warn(filename, &[])
+}
+
+/// `parse_todos_containing`, warning on stderr about lines that could
+/// not be parsed
+pub fn parse_todos_containing_and_warn(
+	filename: &str,
+	words: &[String],
+) -> Result<TodoList, io::Error> {
+	let todos = parse_todos_containing(filename, words)?;
 
-	Ok(TodoList { items: todos })
+	warn_invalid(filename, &todos);
+
+	Ok(todos)
 }
 
-/// Read all todos from the user's default todo.txt file
+/// Read all todos of the archive `filename` and the files it was
+/// rotated into (see `archive::Archive`), warning on stderr about lines
+/// that could not be parsed. Line numbers count across all the files.
+pub fn parse_archive_and_warn(filename: &str) -> Result<TodoList, io::Error> {
+	parse_archive_containing_and_warn(filename, &[])
+}
+
+/// Read the todos of the archive `filename` whose line contains all of
+/// `words`, see `parse_archive_and_warn` and `parse_todos_containing`
+pub fn parse_archive_containing_and_warn(
+	filename: &str,
+	words: &[String],
+) -> Result<TodoList, io::Error> {
+	let archive = Archive::open(filename)?;
+	let todos = read_decoded(
+		Decoded {
+			text: archive.text(),
+			format: Format::default(),
+			errors: vec![],
+		},
+		words,
+	);
+
+	for (path, e) in archive.decode_errors() {
+		eprintln!("warning: {}:{}", path.display(), e);
+	}
+
+	warn_invalid(filename, &todos);
+
+	Ok(todos)
+}
+
+/// Read all todos from the user's default todo.txt file, warning on
+/// stderr about lines that could not be parsed.
 pub fn parse_todos_from_default_file() -> Result<TodoList, io::Error> {
-	parse_todos(&get_todo_filename())
+	parse_todos_and_warn(&get_todo_filename())
 }
 
-/// Write all `todos` to `filename`
+/// Read all todos from the user's default archive.txt file, warning on
+/// stderr about lines that could not be parsed.
+pub fn parse_todos_from_archive_file() -> Result<TodoList, io::Error> {
+	parse_archive_and_warn(&get_archive_filename())
+}
+
+/// Text of all todos of `list`, one per line ending with `\n`
 ///
-/// Warning: This will overwrite `filename`
-pub fn write_todos(filename: &str, todos: &[Todo]) -> Result<(), io::Error> {
-	let mut f = File::create(filename)?;
+/// Invalid lines are written back unchanged, ahead of the todo that
+/// followed them when they were read.
+pub fn serialize_todos(list: &TodoList) -> String {
+	let mut text = String::new();
+	let mut invalid = list.invalid.iter().peekable();
 
-	for t in todos {
-		f.write_all(&format!("{}\n", &t.serialize()).into_bytes())?;
+	for t in &list.items {
+		while let Some(l) = invalid.next_if(|l| l.position <= t.index) {
+			text.push_str(&l.text);
+			text.push('\n');
+		}
+
+		text.push_str(&t.serialize());
+		text.push('\n');
+	}
+
+	for l in invalid {
+		text.push_str(&l.text);
+		text.push('\n');
 	}
 
-	f.flush()?;
+	text
+}
+
+/// Write all todos of `list` to `writer`, one per line, with the line
+/// endings and byte order mark of the file `list` was read from
+pub fn write_todos_to<W: Write>(mut writer: W, list: &TodoList) -> Result<(), io::Error> {
+	writer.write_all(&encode(&serialize_todos(list), list.format))?;
+	writer.flush()
+}
+
+/// Replace the content of `filename` with what `write` writes
+///
+/// The content is written to a temporary file in the same directory,
+/// synced to disk and then renamed over `filename`, so `filename` holds
+/// either its old or its new content even if writing fails half way.
+/// When `filename` is a symlink, the file it points to is replaced.
+pub fn write_file_atomically<F>(filename: &str, write: F) -> Result<(), io::Error>
+where
+	F: FnOnce(&mut BufWriter<&File>) -> Result<(), io::Error>,
+{
+	let path = fs::canonicalize(filename).unwrap_or_else(|_| PathBuf::from(filename));
+	let dir = match path.parent() {
+		Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+		_ => PathBuf::from("."),
+	};
+	let name = path.file_name().unwrap_or_default().to_string_lossy();
+	let tmp_path = dir.join(format!(".{}.{}.tmp", name, std::process::id()));
+
+	let result = (|| {
+		let f = File::create(&tmp_path)?;
+
+		if let Ok(metadata) = fs::metadata(&path) {
+			f.set_permissions(metadata.permissions())?;
+		}
+
+		let mut writer = BufWriter::new(&f);
+		write(&mut writer)?;
+		writer.flush()?;
+		drop(writer);
+
+		f.sync_all()?;
+		fs::rename(&tmp_path, &path)
+	})();
+
+	if result.is_err() {
+		let _ = fs::remove_file(&tmp_path);
+	}
+
+	result?;
+	sync_dir(&dir);
 
 	Ok(())
 }
 
-/// Write all `todos` to the user's default todo.txt file
-pub fn write_todos_to_default_file(todos: &[Todo]) -> Result<(), io::Error> {
-	write_todos(&get_todo_filename(), todos)
+/// Make a rename in `dir` durable. Not all platforms can sync a
+/// directory, so failures are ignored.
+fn sync_dir(dir: &Path) {
+	if let Ok(d) = File::open(dir) {
+		let _ = d.sync_all();
+	}
+}
+
+/// Write all todos of `list` to `filename`
+///
+/// Warning: This will overwrite `filename`. It is replaced atomically,
+/// see `write_file_atomically`. A list read with invalid UTF-8 is not
+/// written, see `encoding::check_rewrite`.
+pub fn write_todos(filename: &str, list: &TodoList) -> Result<(), io::Error> {
+	encoding::check_rewrite(filename, &list.decode_errors)?;
+	write_file_atomically(filename, |w| write_todos_to(w, list))
+}
+
+/// Text of the user's default todo.txt or archive.txt. The archive is
+/// read across all the files it was rotated into.
+pub fn read_default_text(file: JournalFile) -> Result<String, io::Error> {
+	match file {
+		JournalFile::Todo => Ok(encoding::read_file(get_todo_filename())?.text),
+		JournalFile::Archive => Ok(Archive::open(&get_archive_filename())?.text()),
+	}
 }
 
-fn append_todos_to_file(todos: &[Todo], filename: &str) -> Result<(), io::Error> {
+/// Replace the user's default todo.txt or archive.txt with `text`
+///
+/// Both files are snapshotted first, see `backup::backup_default_files`.
+/// todo.txt is written atomically, keeping its line endings. The lines
+/// of the archive stay in the rotated files they are in, see
+/// `archive::Archive::replace`.
+pub fn write_default_text(file: JournalFile, text: &str) -> Result<(), io::Error> {
+	match file {
+		JournalFile::Todo => {
+			let filename = get_todo_filename();
+			let current = encoding::read_file(&filename)?;
+
+			encoding::check_rewrite(&filename, &current.errors)?;
+
+			let bytes = encode(text, current.format);
+
+			backup_default_files()?;
+			write_file_atomically(&filename, |w| w.write_all(&bytes))
+		}
+		JournalFile::Archive => {
+			backup_default_files()?;
+
+			Archive::open(&get_archive_filename())?
+				.replace(text.lines().map(String::from).collect())
+		}
+	}
+}
+
+/// Replace `file` with what `edit` makes of its text, returning the
+/// changed lines for the journal
+fn rewrite_default_text<F>(file: JournalFile, edit: F) -> Result<Vec<Change>, io::Error>
+where
+	F: FnOnce(&str) -> String,
+{
+	let before = read_default_text(file)?;
+	let after = edit(&before);
+
+	write_default_text(file, &after)?;
+
+	Ok(journal::diff(file, &before, &after))
+}
+
+/// Replace `file` with the todos of `list`, returning the changed lines
+/// for the journal
+fn rewrite_default_file(file: JournalFile, list: &TodoList) -> Result<Vec<Change>, io::Error> {
+	rewrite_default_text(file, |_| serialize_todos(list))
+}
+
+/// `text` without the lines of the todos numbered `numbers`, counted as
+/// `Todo::index` is. All other lines are kept as they are.
+fn remove_todo_lines(text: &str, numbers: &[u32]) -> String {
+	let mut count = 0;
+	let mut kept = String::new();
+
+	for line in text.lines() {
+		let trimmed = line.trim_end();
+
+		if !trimmed.is_empty() && Todo::is_todo(trimmed) {
+			count += 1;
+
+			if numbers.contains(&(count - 1)) {
+				continue;
+			}
+		}
+
+		kept.push_str(line);
+		kept.push('\n');
+	}
+
+	kept
+}
+
+/// Write all todos of `list` to the user's default todo.txt file,
+/// after taking a snapshot of it (see `backup::backup_default_files`).
+/// The lines changed are recorded in the journal.
+pub fn write_todos_to_default_file(list: &TodoList) -> Result<(), io::Error> {
+	journal::record(rewrite_default_file(JournalFile::Todo, list)?)
+}
+
+/// Write `list` to todo.txt and remove the todos numbered `numbers`
+/// (see `Todo::index`) from archive.txt, for todos moved back from the
+/// archive. The other lines of the archive are left as they are.
+/// todo.txt is written first so a crash in between leaves a moved todo
+/// in both files rather than in neither.
+pub fn write_unarchived_todos(list: &TodoList, numbers: &[u32]) -> Result<(), io::Error> {
+	let todo_changes = rewrite_default_file(JournalFile::Todo, list)?;
+	let mut changes = rewrite_default_text(JournalFile::Archive, |text| {
+		remove_todo_lines(text, numbers)
+	})?;
+
+	changes.extend(todo_changes);
+	journal::record(changes)
+}
+
+/// true if `f` is empty or its last byte is a newline
+fn ends_with_newline(f: &mut File) -> Result<bool, io::Error> {
+	if f.metadata()?.len() == 0 {
+		return Ok(true);
+	}
+
+	let mut last = [0; 1];
+	f.seek(SeekFrom::End(-1))?;
+	f.read_exact(&mut last)?;
+
+	Ok(last[0] == b'\n')
+}
+
+/// Append todos to `filename` in a single write, synced to disk
+///
+/// If an earlier write left the file without a final newline, one is
+/// added first so the partial line and the new todos stay separate.
+/// Lines end the way the first line of the file does.
+pub fn append_todos_to_file(todos: &[Todo], filename: &str) -> Result<(), io::Error> {
+	let line_ending = encoding::detect_file(filename)?.line_ending();
 	let mut f = OpenOptions::new()
+		.read(true)
 		.append(true)
 		.create(true)
 		.open(filename)?;
 
+	let mut content = String::new();
+
+	if !ends_with_newline(&mut f)? {
+		content.push_str(line_ending);
+	}
+
 	for t in todos {
-		f.write_all(&format!("{}\n", t.serialize()).into_bytes())?;
+		content.push_str(&t.serialize());
+		content.push_str(line_ending);
 	}
 
-	Ok(())
+	f.write_all(content.as_bytes())?;
+	f.sync_all()
 }
 
-/// Append todos to the user's default archive.txt file
-pub fn append_todos_to_archive_file(todos: &[Todo]) -> Result<(), io::Error> {
-	append_todos_to_file(todos, &get_archive_filename())
-}
+/// Append todos to the default file of `file` and record them in the
+/// journal. Appending to the archive moves todos out of todo.txt, so
+/// both files are snapshotted first as they are for a rewrite.
+fn append_todos_to_default(todos: &[Todo], file: JournalFile) -> Result<(), io::Error> {
+	match file {
+		JournalFile::Todo => append_todos_to_file(todos, &get_todo_filename())?,
+		JournalFile::Archive => {
+			let filename = get_archive_filename();
 
-fn write_todo_to_file(mut f: std::fs::File, todo: &Todo) -> Result<(), io::Error> {
-	f.write_all(&format!("{}\n", todo.serialize()).into_bytes())?;
+			backup_default_files()?;
+			append_todos_to_file(todos, &current_file(&filename).path.to_string_lossy())?;
+			compress_old_files(&filename)?;
+		}
+	}
 
-	Ok(())
+	let lines: Vec<String> = todos.iter().map(|t| t.serialize()).collect();
+
+	journal::record_append(file, &lines)
+}
+
+/// Append todos to the user's default archive.txt file
+pub fn append_todos_to_archive_file(todos: &[Todo]) -> Result<(), io::Error> {
+	append_todos_to_default(todos, JournalFile::Archive)
 }
 
 /// Append a single todo to `filename`
 pub fn append_todo_to_file(todo: &Todo, filename: &str) -> Result<(), io::Error> {
-	let f = OpenOptions::new()
-		.append(true)
-		.create(true)
-		.open(filename)?;
-
-	write_todo_to_file(f, todo)?;
-
-	Ok(())
+	append_todos_to_file(std::slice::from_ref(todo), filename)
 }
 
 /// Append a single todo to the user's default todo.txt file
 pub fn append_todo_to_default_file(todo: &Todo) -> Result<(), io::Error> {
-	append_todo_to_file(todo, &get_todo_filename())
+	append_todos_to_default(std::slice::from_ref(todo), JournalFile::Todo)
 }
 
 /// Append a single todo to the user's default archive.txt file
 pub fn append_todo_to_archive_file(todo: &Todo) -> Result<(), io::Error> {
-	append_todo_to_file(todo, &get_archive_filename())
+	append_todos_to_default(std::slice::from_ref(todo), JournalFile::Archive)
 }
 
 /// Get the last inserted task number
+///
+/// Lines that are not valid todos are not numbered, so they are not
+/// counted.
 pub fn last_inserted_todo_number() -> Result<i64, io::Error> {
-	let filename = get_todo_filename();
-	let fh = File::open(filename)?;
+	let todos = parse_todos(&get_todo_filename())?;
+
+	Ok(todos.items.len().try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_util::scratch_file;
+	use uuid::Uuid;
+
+	const ID: &str = "id:2a6a3d2e-4c0a-4c6e-9d3b-7a1f3b8d7c11";
+
+	fn round_trip(text: &str) -> String {
+		let list = read_todos(text.as_bytes()).unwrap();
+		let mut out = Vec::new();
+
+		write_todos_to(&mut out, &list).unwrap();
+
+		String::from_utf8(out).unwrap()
+	}
 
-	let file = BufReader::new(&fh);
-	let lines = file.lines();
+	#[test]
+	fn read_keeps_invalid_lines() {
+		let text = format!("first {}\n(C) 2021-99-99 bad date\nsecond {}\n", ID, ID);
+		let list = read_todos(text.as_bytes()).unwrap();
 
-	return Ok(lines.count().try_into().unwrap());
+		assert_eq!(list.items.len(), 2);
+		assert_eq!(list.invalid.len(), 1);
+		assert_eq!(list.invalid[0].error.line, 2);
+		assert_eq!(list.invalid[0].text, "(C) 2021-99-99 bad date");
+	}
+
+	#[test]
+	fn read_derives_same_ids_again() {
+		let text = "call mom\ncall mom\nwater plants\n";
+		let ids = || -> Vec<Uuid> {
+			read_todos(text.as_bytes())
+				.unwrap()
+				.items
+				.iter()
+				.map(|t| t.id)
+				.collect()
+		};
+
+		assert_eq!(ids(), ids());
+		assert_ne!(ids()[0], ids()[1]);
+	}
+
+	#[test]
+	fn read_containing_keeps_numbers() {
+		let text = "mow +Garden\n(C) 2021-99-99 bad date\nfile +Taxes\n\nweed +Garden\n";
+		let list = read_decoded(decode(text.as_bytes()), &[String::from("+Garden")]);
+		let numbers: Vec<u32> = list.items.iter().map(|t| t.index).collect();
+
+		assert_eq!(numbers, vec![0, 2]);
+		assert_eq!(list.invalid.len(), 1);
+		assert_eq!(list.invalid[0].position, 1);
+	}
+
+	#[test]
+	fn check_reports_line_numbers() {
+		let text = format!(
+			"first {}\n\n2021-99-99 bad\nsecond due:x {}\nthird id:nope\n",
+			ID, ID
+		);
+		let errors: Vec<String> = check_todos(text.as_bytes())
+			.unwrap()
+			.iter()
+			.map(|e| e.to_string())
+			.collect();
+
+		assert_eq!(
+			errors,
+			vec![
+				"3:1: invalid date '2021-99-99'",
+				"4:8: invalid date 'x'",
+				"4:14: duplicate id '2a6a3d2e-4c0a-4c6e-9d3b-7a1f3b8d7c11', first used on line 1",
+				"5:7: invalid id 'nope'",
+			]
+		);
+	}
+
+	#[test]
+	fn write_puts_invalid_lines_back_in_place() {
+		let text = format!(
+			"(C) 2021-99-99 bad\nfirst {}\nx 2021-01-01 2021-13-01 bad\nsecond {}\n",
+			ID, ID
+		);
+
+		assert_eq!(round_trip(&text), text);
+	}
+
+	#[test]
+	fn write_keeps_trailing_whitespace_of_invalid_lines() {
+		let text = format!("first {}\n(C) 2021-99-99 bad date \t \n", ID);
+
+		assert_eq!(round_trip(&text), text);
+	}
+
+	#[test]
+	fn remove_todo_lines_keeps_other_lines() {
+		let text = "x done one\n\n2021-99-99 bad  \nx done two  \nx done three\n";
+		let list = read_todos(text.as_bytes()).unwrap();
+
+		assert_eq!(list.items[1].task(), "done two");
+		assert_eq!(
+			remove_todo_lines(text, &[list.items[1].index]),
+			"x done one\n\n2021-99-99 bad  \nx done three\n"
+		);
+	}
+
+	#[test]
+	fn write_keeps_invalid_lines_when_todos_are_removed() {
+		let text = format!("first {}\n2021-99-99 bad\n", ID);
+		let mut list = read_todos(text.as_bytes()).unwrap();
+		list.items.clear();
+
+		let mut out = Vec::new();
+		write_todos_to(&mut out, &list).unwrap();
+
+		assert_eq!(String::from_utf8(out).unwrap(), "2021-99-99 bad\n");
+	}
+
+	#[test]
+	fn read_crlf_bom_and_invalid_utf8() {
+		let bytes = b"\xef\xbb\xbf(A) call mom due:2021-12-25  \r\nbuy m\xffilk\r\n".to_vec();
+		let list = read_todos(bytes.as_slice()).unwrap();
+
+		assert_eq!(list.items[0].task(), "call mom");
+		assert_eq!(list.items[0].key_value("due"), Some("2021-12-25"));
+		assert_eq!(list.items[1].task(), "buy m\u{fffd}ilk");
+		assert_eq!(list.decode_errors.len(), 1);
+		assert_eq!(list.decode_errors[0].line, 2);
+
+		let mut out = Vec::new();
+		write_todos_to(&mut out, &list).unwrap();
+
+		assert!(out.starts_with(b"\xef\xbb\xbf(A) call mom due:2021-12-25"));
+		assert_eq!(out.iter().filter(|b| **b == b'\r').count(), 2);
+		assert_eq!(out.iter().filter(|b| **b == b'\n').count(), 2);
+	}
+
+	#[test]
+	fn write_refuses_invalid_utf8() {
+		let filename = scratch_file("latin1.txt");
+		let bytes = b"caf\xe9 au lait\nsecond\n";
+		fs::write(&filename, bytes).unwrap();
+
+		let mut list = parse_todos(&filename).unwrap();
+		list.items[1].priority = Some('A');
+
+		let e = write_todos(&filename, &list).unwrap_err();
+
+		assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+		assert!(e.to_string().contains(":1:4: invalid UTF-8"));
+		assert_eq!(fs::read(&filename).unwrap(), bytes.to_vec());
+
+		fs::remove_file(&filename).unwrap();
+	}
+
+	#[test]
+	fn write_todos_replaces_file() {
+		let filename = scratch_file("write.txt");
+		fs::write(&filename, "old content\n").unwrap();
+
+		let list = read_todos(format!("first {}\n", ID).as_bytes()).unwrap();
+		write_todos(&filename, &list).unwrap();
+
+		assert_eq!(
+			fs::read_to_string(&filename).unwrap(),
+			format!("first {}\n", ID)
+		);
+
+		let dir = Path::new(&filename).parent().unwrap();
+		let leftovers = fs::read_dir(dir)
+			.unwrap()
+			.filter_map(|e| e.ok())
+			.filter(|e| e.file_name().to_string_lossy().starts_with(".todo-txt-"))
+			.count();
+		assert_eq!(leftovers, 0);
+
+		fs::remove_file(&filename).unwrap();
+	}
+
+	#[test]
+	fn append_after_partial_line() {
+		let filename = scratch_file("append.txt");
+		fs::write(&filename, "partial").unwrap();
+
+		let todo = format!("first {}", ID).parse::<Todo>().unwrap();
+		append_todo_to_file(&todo, &filename).unwrap();
+
+		assert_eq!(
+			fs::read_to_string(&filename).unwrap(),
+			format!("partial\nfirst {}\n", ID)
+		);
+
+		fs::remove_file(&filename).unwrap();
+	}
+
+	#[test]
+	fn parse_missing_file_is_empty() {
+		let list = parse_todos(&scratch_file("missing.txt")).unwrap();
+
+		assert!(list.items.is_empty());
+		assert!(list.invalid.is_empty());
+	}
+
+	#[test]
+	fn lock_is_exclusive() {
+		let filename = scratch_file("lock.txt");
+		let timeout = Duration::from_millis(100);
+
+		let lock = lock_file(&filename, timeout).unwrap();
+		let err = lock_file(&filename, timeout).unwrap_err();
+		assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+		drop(lock);
+		assert!(lock_file(&filename, timeout).is_ok());
+
+		fs::remove_file(format!("{}.lock", filename)).unwrap();
+	}
 }
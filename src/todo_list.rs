@@ -1,11 +1,179 @@
+use crate::encoding::Format;
+use crate::parse_error::ParseError;
+use crate::query::{Query, QueryError};
 use crate::todo::Todo;
+use std::error::Error;
+use std::fmt;
+
+/// Shortest all digit id prefix that is also matched against ids. Shorter
+/// numbers are only line numbers.
+pub const MIN_NUMERIC_ID_PREFIX: usize = 4;
+
+/// Why a todo could not be selected
+#[derive(Debug, PartialEq, Eq)]
+pub enum SelectError {
+	/// No todo has the given number or id
+	NotFound(String),
+
+	/// More than one todo matches, with the numbers of those that do
+	Ambiguous(String, Vec<u32>),
+
+	/// The query selecting todos could not be parsed
+	InvalidQuery(QueryError),
+}
+
+impl fmt::Display for SelectError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			SelectError::NotFound(s) => write!(f, "no todo matches '{}'", s),
+			SelectError::Ambiguous(s, numbers) => {
+				let numbers: Vec<String> = numbers.iter().map(|n| n.to_string()).collect();
+
+				write!(
+					f,
+					"'{}' is ambiguous, it matches todos {}",
+					s,
+					numbers.join(", ")
+				)
+			}
+			SelectError::InvalidQuery(e) => write!(f, "{}", e),
+		}
+	}
+}
+
+impl Error for SelectError {}
+
+/// A line of a todo file that could not be parsed as a todo
+#[derive(Clone, Debug)]
+pub struct InvalidLine {
+	/// Number of valid todos that preceded this line in the file
+	pub position: u32,
+
+	/// The line exactly as it was read
+	pub text: String,
+
+	/// Why the line could not be parsed, including its line number
+	pub error: ParseError,
+}
 
 /// A list of Todo items
 pub struct TodoList {
 	pub items: Vec<Todo>,
+
+	/// Lines that could not be parsed. They are kept so that they can be
+	/// written back unchanged.
+	pub invalid: Vec<InvalidLine>,
+
+	/// How the file was written, to write it back the same way
+	pub format: Format,
+
+	/// Invalid UTF-8 found reading the file. The lines were read with
+	/// U+FFFD in place of the invalid bytes.
+	pub decode_errors: Vec<ParseError>,
+
+	/// Todos whose short id is that of an earlier todo, see
+	/// `Todo::short_id`. They can not be told apart by id.
+	pub id_errors: Vec<ParseError>,
+}
+
+/// Sort key ordering todos as `Todo::cmp` does. Sorting by
+/// key works out the task text of each todo once, rather than on every
+/// comparison, which is slow on large lists.
+fn priority_key(t: &Todo) -> (bool, bool, Option<char>, String) {
+	(t.is_complete, t.priority.is_none(), t.priority, t.task())
 }
 
 impl TodoList {
+	pub fn new(items: Vec<Todo>) -> TodoList {
+		TodoList {
+			items,
+			invalid: vec![],
+			format: Format::default(),
+			decode_errors: vec![],
+			id_errors: vec![],
+		}
+	}
+
+	/// Find the position in `items` of the todo selected by `selector`:
+	/// its number as shown by `ls`, or a unique prefix of its id. Prefix
+	/// the selector with `id:` to only match ids.
+	pub fn find(&self, selector: &str) -> Result<usize, SelectError> {
+		let (id_prefix, is_number) = match selector.strip_prefix("id:") {
+			Some(prefix) => (prefix, false),
+			None => (selector, selector.chars().all(|c| c.is_ascii_digit())),
+		};
+		let mut matches = Vec::new();
+
+		if is_number {
+			if let Ok(number) = selector.parse::<u32>() {
+				matches.extend(self.items.iter().position(|t| t.index + 1 == number));
+			}
+		}
+
+		if !id_prefix.is_empty() && (!is_number || id_prefix.len() >= MIN_NUMERIC_ID_PREFIX) {
+			let id_prefix = id_prefix.to_ascii_lowercase();
+
+			for (i, t) in self.items.iter().enumerate() {
+				if t.id.to_string().starts_with(&id_prefix) && !matches.contains(&i) {
+					matches.push(i);
+				}
+			}
+		}
+
+		match matches.len() {
+			0 => Err(SelectError::NotFound(selector.to_string())),
+			1 => Ok(matches[0]),
+			_ => Err(SelectError::Ambiguous(
+				selector.to_string(),
+				matches.iter().map(|i| self.items[*i].index + 1).collect(),
+			)),
+		}
+	}
+
+	/// Find the positions of all todos selected by `selectors`. See `find`.
+	pub fn find_all(&self, selectors: &[String]) -> Result<Vec<usize>, SelectError> {
+		selectors.iter().map(|s| self.find(s)).collect()
+	}
+
+	/// Find the positions of the todos selected by `selectors`, see `find`,
+	/// followed by those of the other todos matching `query`, see
+	/// `Query`. An empty query selects nothing more, a query matching no
+	/// todo is an error.
+	pub fn select(&self, selectors: &[String], query: &str) -> Result<Vec<usize>, SelectError> {
+		let mut positions = self.find_all(selectors)?;
+
+		if query.trim().is_empty() {
+			return Ok(positions);
+		}
+
+		let compiled = Query::parse(&[query]).map_err(SelectError::InvalidQuery)?;
+		let matching: Vec<usize> = (0..self.items.len())
+			.filter(|i| compiled.matches(&self.items[*i]))
+			.collect();
+
+		if matching.is_empty() {
+			return Err(SelectError::NotFound(query.to_string()));
+		}
+
+		for i in matching {
+			if !positions.contains(&i) {
+				positions.push(i);
+			}
+		}
+
+		Ok(positions)
+	}
+
+	fn filter<P>(mut self, predicate: P) -> TodoList
+	where
+		P: FnMut(&Todo) -> bool,
+	{
+		self.items.retain(predicate);
+		self
+	}
+
+	/// Split into active and completed todos. Invalid lines stay with the
+	/// active list.
 	pub fn split(self) -> (TodoList, TodoList) {
 		let mut active_list = vec![];
 		let mut inactive_list = vec![];
@@ -19,10 +187,14 @@ impl TodoList {
 		}
 
 		(
-			TodoList { items: active_list },
 			TodoList {
-				items: inactive_list,
+				items: active_list,
+				invalid: self.invalid,
+				format: self.format,
+				decode_errors: self.decode_errors,
+				id_errors: self.id_errors,
 			},
+			TodoList::new(inactive_list),
 		)
 	}
 
@@ -30,63 +202,223 @@ impl TodoList {
 		self.filter_by_text(format!("+{}", name).as_str())
 	}
 
+	/// Keep todos tagged with `name` (with or without `#`)
+	pub fn filter_by_tag(self, name: &str) -> TodoList {
+		self.filter(|t| t.has_tag(name))
+	}
+
 	pub fn filter_by_priority(self, priority: char) -> TodoList {
-		TodoList {
-			items: self
-				.items
-				.into_iter()
-				.filter(|t| t.priority.is_some() && t.priority.unwrap() <= priority)
-				.collect(),
-		}
+		self.filter(|t| t.priority.is_some() && t.priority.unwrap() <= priority)
 	}
 
 	pub fn filter_by_complete(self, complete: bool) -> TodoList {
-		TodoList {
-			items: self
-				.items
-				.into_iter()
-				.filter(|t| t.is_complete == complete)
-				.collect(),
-		}
+		self.filter(|t| t.is_complete == complete)
 	}
 
 	pub fn filter_by_past_due(self, is_past_due: bool) -> TodoList {
-		TodoList {
-			items: self
-				.items
-				.into_iter()
-				.filter(|t| t.is_complete == false && t.is_past_due() == is_past_due)
-				.collect(),
-		}
+		self.filter(|t| !t.is_complete && t.is_past_due() == is_past_due)
 	}
 
+	/// Keep todos whose threshold date (t:) has or has not been reached
+	pub fn filter_by_threshold(self, is_reached: bool) -> TodoList {
+		self.filter(|t| t.is_threshold_reached() == is_reached)
+	}
+
+	/// Keep todos whose line contains `text`, or does not contain it
+	/// when `text` starts with `-`
 	pub fn filter_by_text(self, text: &str) -> TodoList {
-		let mut search_text = text.to_string();
-		let mut compare_result = true;
+		self.filter_by_texts(&[text])
+	}
 
-		if search_text.starts_with("-") {
-			search_text = search_text.replace("-", "");
-			compare_result = false;
+	/// Keep todos matching all of `texts`, see `filter_by_text`. The line
+	/// of a todo is serialized once and then searched for each text.
+	pub fn filter_by_texts<S: AsRef<str>>(self, texts: &[S]) -> TodoList {
+		let terms: Vec<(String, bool)> = texts
+			.iter()
+			.map(|text| {
+				let text = text.as_ref();
+
+				match text.strip_prefix('-') {
+					Some(negated) if !negated.is_empty() => (negated.to_string(), false),
+					_ => (text.to_string(), true),
+				}
+			})
+			.collect();
+
+		if terms.is_empty() {
+			return self;
 		}
 
-		TodoList {
-			items: self
-				.items
-				.into_iter()
-				.filter(|t| t.serialize().contains(&search_text) == compare_result)
-				.collect(),
+		self.filter(|t| {
+			let line = t.serialize();
+
+			terms
+				.iter()
+				.all(|(search_text, compare_result)| line.contains(search_text) == *compare_result)
+		})
+	}
+
+	/// Keep todos matching `query`
+	pub fn filter_by_query(self, query: &Query) -> TodoList {
+		if query.is_empty() {
+			return self;
 		}
+
+		self.filter(|t| query.matches(t))
 	}
 
+	/// Order as `Todo::cmp` does
 	pub fn sort(&mut self) {
-		self.items.sort_by(|a, b| a.cmp(b));
+		self.items.sort_by_cached_key(priority_key);
 	}
 
+	/// Order as `Todo::cmp_by_title` does
 	pub fn sort_by_title(&mut self) {
-		self.items.sort_by(|a, b| a.cmp_by_title(b));
+		self.items.sort_by_cached_key(|t| t.task());
 	}
 
+	/// Order as `Todo::cmp_by_due_date` does
 	pub fn sort_by_due_date(&mut self) {
-		self.items.sort_by(|a, b| a.cmp_by_due_date(b));
+		self.items.sort_by_cached_key(|t| {
+			let due = t.due().ok().flatten();
+			let (is_complete, no_priority, priority, task) = priority_key(t);
+
+			(is_complete, due.is_none(), due, no_priority, priority, task)
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::cmp::Ordering;
+
+	fn list(lines: &[&str]) -> TodoList {
+		TodoList::new(
+			lines
+				.iter()
+				.enumerate()
+				.map(|(i, l)| {
+					let mut t = l.parse::<Todo>().unwrap();
+					t.index = i as u32;
+					t
+				})
+				.collect(),
+		)
+	}
+
+	fn sample() -> TodoList {
+		list(&[
+			"one id:3a6a3d2e-4c0a-4c6e-9d3b-7a1f3b8d7c11",
+			"two id:3b6a3d2e-4c0a-4c6e-9d3b-7a1f3b8d7c11",
+			"three id:12345678-4c0a-4c6e-9d3b-7a1f3b8d7c11",
+		])
+	}
+
+	#[test]
+	fn find_by_number() {
+		assert_eq!(sample().find("2"), Ok(1));
+		assert_eq!(
+			sample().find("4"),
+			Err(SelectError::NotFound("4".to_string()))
+		);
+	}
+
+	#[test]
+	fn find_by_id_prefix() {
+		assert_eq!(sample().find("3b"), Ok(1));
+		assert_eq!(sample().find("3A6A"), Ok(0));
+		assert_eq!(sample().find("1234"), Ok(2));
+		assert_eq!(
+			sample().find("id:3"),
+			Err(SelectError::Ambiguous("id:3".to_string(), vec![1, 2]))
+		);
+	}
+
+	#[test]
+	fn select_by_number_and_query() {
+		let todos = list(&["call mom +Family", "buy milk", "plan trip +Family-Vacation"]);
+
+		assert_eq!(
+			todos.select(&["2".to_string()], "+Family or milk"),
+			Ok(vec![1, 0, 2])
+		);
+		assert_eq!(todos.select(&[], "project:Family"), Ok(vec![0]));
+		assert_eq!(
+			todos.select(&[], "+Work"),
+			Err(SelectError::NotFound("+Work".to_string()))
+		);
+		assert!(matches!(
+			todos.select(&[], "(+Work"),
+			Err(SelectError::InvalidQuery(_))
+		));
+	}
+
+	#[test]
+	fn filter_by_texts_keeps_hyphens() {
+		let matching = |text: &str| -> Vec<u32> {
+			list(&["call mom +Family", "plan trip +Family-Vacation"])
+				.filter_by_texts(&[text])
+				.items
+				.iter()
+				.map(|t| t.index)
+				.collect()
+		};
+
+		assert_eq!(matching("-+Family-Vacation"), vec![0]);
+		assert_eq!(matching("+Family-Vacation"), vec![1]);
+	}
+
+	#[test]
+	fn sort_matches_comparisons() {
+		let lines = [
+			"x (B) done b",
+			"(B) 2021-01-01 b due:2021-03-01",
+			"no priority due:2021-02-01",
+			"(A) a",
+			"x done a due:2021-01-01",
+			"(B) a due:2021-03-01",
+			"another",
+			"(A) a due:2021-01-01",
+		];
+		let tasks =
+			|todos: &TodoList| -> Vec<u32> { todos.items.iter().map(|t| t.index).collect() };
+		let sorted_by = |cmp: fn(&Todo, &Todo) -> Ordering| {
+			let mut todos = list(&lines);
+			todos.items.sort_by(cmp);
+			tasks(&todos)
+		};
+
+		let mut todos = list(&lines);
+		todos.sort();
+		assert_eq!(tasks(&todos), sorted_by(Todo::cmp));
+
+		let mut todos = list(&lines);
+		todos.sort_by_title();
+		assert_eq!(tasks(&todos), sorted_by(Todo::cmp_by_title));
+
+		let mut todos = list(&lines);
+		todos.sort_by_due_date();
+		assert_eq!(tasks(&todos), sorted_by(Todo::cmp_by_due_date));
+	}
+
+	#[test]
+	fn find_number_and_id_prefix_is_ambiguous() {
+		let mut todos = sample();
+
+		for i in 0..1233 {
+			todos.items.push(list(&["filler"]).items.remove(0));
+			let filler = todos.items.last_mut().unwrap();
+
+			// a fixed id, a random one could start with 1234 as well
+			filler.id = uuid::Uuid::nil();
+			filler.index = 3 + i;
+		}
+
+		assert!(matches!(
+			todos.find("1234"),
+			Err(SelectError::Ambiguous(_, _))
+		));
+		assert_eq!(todos.find("id:1234"), Ok(2));
 	}
 }
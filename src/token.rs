@@ -0,0 +1,253 @@
+/// Split the text of a todo into tokens, keeping the original layout
+use std::borrow::Cow;
+use std::fmt;
+
+/// URI schemes that are not followed by `//`. Words using them are text,
+/// not key value attributes.
+const URI_SCHEMES: [&str; 10] = [
+	"data", "geo", "magnet", "mailto", "news", "sip", "sms", "tel", "urn", "xmpp",
+];
+
+/// true if `key` can be the key of a key value attribute: a letter
+/// followed by letters, digits, `_` or `-`.
+pub(crate) fn is_key(key: &str) -> bool {
+	let mut chars = key.chars();
+
+	match chars.next() {
+		Some(c) if c.is_ascii_alphabetic() => {}
+		_ => return false,
+	}
+
+	chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// true if `key:value` looks like a URL or other URI rather than an
+/// attribute, e.g. `https://example.com` or `mailto:me@example.com`
+fn is_uri(key: &str, value: &str) -> bool {
+	value.starts_with("//") || URI_SCHEMES.iter().any(|s| s.eq_ignore_ascii_case(key))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// What a single word of a todo means
+pub enum TokenKind {
+	/// Plain text
+	Word(String),
+
+	/// Project tag, including the leading `+`
+	Project(String),
+
+	/// Context tag, including the leading `@`
+	Context(String),
+
+	/// Hashtag, including the leading `#`
+	Tag(String),
+
+	/// Key value attribute (key:value)
+	KeyValue(String, String),
+}
+
+impl TokenKind {
+	/// Classify a single word (a run of non whitespace characters).
+	///
+	/// `key:value` is only an attribute when `key` is a bare identifier
+	/// and the word is not a URL; everything else is plain text.
+	pub fn parse(word: &str) -> TokenKind {
+		if word.len() > 1 && word.starts_with('+') {
+			return TokenKind::Project(word.to_string());
+		}
+
+		if word.len() > 1 && word.starts_with('@') {
+			return TokenKind::Context(word.to_string());
+		}
+
+		if word.len() > 1 && word.starts_with('#') {
+			return TokenKind::Tag(word.to_string());
+		}
+
+		if let Some((key, value)) = word.split_once(':') {
+			if !value.is_empty() && is_key(key) && !is_uri(key, value) {
+				return TokenKind::KeyValue(key.to_string(), value.to_string());
+			}
+		}
+
+		TokenKind::Word(word.to_string())
+	}
+
+	/// Append the word to `out`, as `Display` writes it
+	pub fn push_to(&self, out: &mut String) {
+		match self {
+			TokenKind::Word(w)
+			| TokenKind::Project(w)
+			| TokenKind::Context(w)
+			| TokenKind::Tag(w) => out.push_str(w),
+			TokenKind::KeyValue(k, v) => {
+				out.push_str(k);
+				out.push(':');
+				out.push_str(v);
+			}
+		}
+	}
+}
+
+impl fmt::Display for TokenKind {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			TokenKind::Word(w)
+			| TokenKind::Project(w)
+			| TokenKind::Context(w)
+			| TokenKind::Tag(w) => write!(f, "{}", w),
+			TokenKind::KeyValue(k, v) => write!(f, "{}:{}", k, v),
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// A word of a todo along with the whitespace that preceded it
+pub struct Token {
+	/// Whitespace found before the word in the original line. The usual
+	/// single space is not allocated for every word.
+	pub space: Cow<'static, str>,
+
+	/// The word itself
+	pub kind: TokenKind,
+}
+
+impl Token {
+	/// Create a token separated from its predecessor by a single space
+	pub fn new(kind: TokenKind) -> Token {
+		Token {
+			space: Cow::Borrowed(" "),
+			kind,
+		}
+	}
+}
+
+/// Split `text` into tokens, remembering the whitespace between them
+pub fn tokenize(text: &str) -> Vec<Token> {
+	let mut tokens = Vec::new();
+	let mut rest = text;
+
+	loop {
+		let word_start = rest
+			.find(|c: char| !c.is_whitespace())
+			.unwrap_or(rest.len());
+		let (space, after_space) = rest.split_at(word_start);
+
+		if after_space.is_empty() {
+			break;
+		}
+
+		let word_end = after_space
+			.find(char::is_whitespace)
+			.unwrap_or(after_space.len());
+		let (word, after_word) = after_space.split_at(word_end);
+
+		tokens.push(Token {
+			space: match space {
+				"" => Cow::Borrowed(""),
+				" " => Cow::Borrowed(" "),
+				_ => Cow::Owned(space.to_string()),
+			},
+			kind: TokenKind::parse(word),
+		});
+
+		rest = after_word;
+	}
+
+	tokens
+}
+
+/// Join `tokens` back into text. Leading whitespace of the first token
+/// is dropped.
+pub fn join<'a, I>(tokens: I) -> String
+where
+	I: IntoIterator<Item = &'a Token>,
+{
+	let mut out = String::new();
+
+	for t in tokens {
+		push(&mut out, &t.space, &t.kind);
+	}
+
+	out
+}
+
+/// Append a token of `kind` preceded by `space` to `out`, the way `join`
+/// does. `space` is dropped while `out` is empty.
+pub fn push(out: &mut String, space: &str, kind: &TokenKind) {
+	if !out.is_empty() {
+		out.push_str(space);
+	}
+
+	kind.push_to(out);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn tokenize_kinds() {
+		let tokens = tokenize("Call mom +Family @phone #urgent due:2021-01-01");
+		let kinds: Vec<TokenKind> = tokens.into_iter().map(|t| t.kind).collect();
+
+		assert_eq!(
+			kinds,
+			vec![
+				TokenKind::Word("Call".to_string()),
+				TokenKind::Word("mom".to_string()),
+				TokenKind::Project("+Family".to_string()),
+				TokenKind::Context("@phone".to_string()),
+				TokenKind::Tag("#urgent".to_string()),
+				TokenKind::KeyValue("due".to_string(), "2021-01-01".to_string()),
+			]
+		);
+	}
+
+	#[test]
+	fn tokenize_lone_symbols_are_words() {
+		let tokens = tokenize("a + b @ c # d :x y:");
+
+		assert!(tokens.iter().all(|t| matches!(t.kind, TokenKind::Word(_))));
+	}
+
+	#[test]
+	fn tokenize_urls_are_words() {
+		for word in [
+			"https://example.com/x",
+			"http://example.com:8080/a?b=c",
+			"file:///tmp/x",
+			"mailto:me@example.com",
+			"urn:isbn:0451450523",
+			"a.b:c",
+			"2fa:x",
+			"(see:x",
+		] {
+			assert_eq!(TokenKind::parse(word), TokenKind::Word(word.to_string()));
+		}
+	}
+
+	#[test]
+	fn tokenize_key_values_with_colons() {
+		assert_eq!(
+			TokenKind::parse("time:10:30"),
+			TokenKind::KeyValue("time".to_string(), "10:30".to_string())
+		);
+		assert_eq!(
+			TokenKind::parse("my_key-2:v"),
+			TokenKind::KeyValue("my_key-2".to_string(), "v".to_string())
+		);
+	}
+
+	#[test]
+	fn join_keeps_layout() {
+		let text = "hello   world\tdue:2021-01-01 +x";
+
+		assert_eq!(join(&tokenize(text)), text);
+	}
+
+	#[test]
+	fn join_drops_leading_space() {
+		assert_eq!(join(&tokenize("  hello world")), "hello world");
+	}
+}
@@ -0,0 +1,62 @@
+//! `todo-txt history` run on a todo.txt that does not store ids
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Empty data directory with a configuration using it and `id_strategy`
+fn data_dir(name: &str, id_strategy: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("todo-txt-{}-{}", name, std::process::id()));
+
+	let _ = fs::remove_dir_all(&dir);
+	fs::create_dir_all(&dir).unwrap();
+	fs::write(
+		dir.join("cfg.toml"),
+		format!(
+			"data_path = {:?}\nid_strategy = {:?}\nbackup_count = 0\n",
+			dir.to_string_lossy(),
+			id_strategy
+		),
+	)
+	.unwrap();
+
+	dir
+}
+
+fn run(dir: &Path, args: &[&str]) -> String {
+	let output = Command::new(env!("CARGO_BIN_EXE_todo-txt"))
+		.arg("--config")
+		.arg(dir.join("cfg.toml"))
+		.args(args)
+		.output()
+		.unwrap();
+
+	assert!(output.status.success(), "todo-txt {:?} failed", args);
+
+	String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn history_without_stored_ids() {
+	let dir = data_dir("history", "none");
+
+	run(&dir, &["add", "call", "mom", "+Family"]);
+	run(&dir, &["add", "water", "plants"]);
+	run(&dir, &["pri", "1", "-p", "A"]);
+	run(&dir, &["tag", "urgent", "1"]);
+	run(&dir, &["do", "1"]);
+
+	let history = run(&dir, &["history", "1"]);
+	let actions: Vec<&str> = history
+		.lines()
+		.filter(|l| !l.starts_with(' '))
+		.filter_map(|l| l.split_whitespace().nth(2))
+		.collect();
+
+	assert_eq!(
+		actions,
+		vec!["created", "reprioritized", "tagged", "completed"]
+	);
+	assert!(!history.contains("water plants"));
+
+	fs::remove_dir_all(&dir).unwrap();
+}